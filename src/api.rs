@@ -1,51 +1,317 @@
 use core::fmt;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
-use anyhow::{ensure, Context, Result};
-use reqwest::{header::ACCEPT, Client};
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use reqwest::{
+    header::{ACCEPT, RETRY_AFTER},
+    Client, Method, Response, StatusCode,
+};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
 
-use crate::json::{radarr, sonarr};
+use crate::{
+    arr::{ArrKind, Kind, MediaItem, QueueJson},
+    config::deserialize_duration,
+    json::{self, command::Command, system, tag::Tag, Parsed},
+    reason::FailureReason,
+    status::{DownloadProtocol, QueueStatus, TrackedDownloadState, TrackedDownloadStatus},
+};
+
+/// Timeouts and retries for requests to an instance -- Ex:
+///
+/// ```toml
+/// [http]
+/// connect_timeout = "10s"
+/// read_timeout = "1m"
+/// retries = 3
+/// retry_backoff = "500ms"
+/// ```
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub connect_timeout: Option<Duration>,
+    /// Longest wait for any read of a response
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub read_timeout: Option<Duration>,
+    /// Retries after the first attempt
+    pub retries: Option<u32>,
+    /// Wait before the first retry, doubled for each one after it
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub retry_backoff: Option<Duration>,
+}
+
+/// Flags sent when removing a record from the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RemovalFlags {
+    pub remove_from_client: bool,
+    pub blocklist: bool,
+    pub change_category: bool,
+    pub skip_redownload: bool,
+}
+
+impl fmt::Display for RemovalFlags {
+    /// Formatted as the query parameters of the request
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "removeFromClient={}&blocklist={}&changeCategory={}&skipRedownload={}",
+            self.remove_from_client, self.blocklist, self.change_category, self.skip_redownload
+        )
+    }
+}
 
 pub struct Api {
     source_url: String,
     api_key: String,
-    radarr: bool,
-    skip_redownload: bool,
+    /// Kind the instance was configured as, detected when not set
+    kind: Option<Kind>,
+    arr: OnceCell<&'static dyn ArrKind>,
+    page_size: i64,
+    /// Report fields of each response that deviate from the models
+    diagnose: bool,
+    /// Fetch the queue with each record's series, movie, artist or author
+    include_parent: bool,
+    retries: u32,
+    retry_backoff: Duration,
 
     client: Client,
 }
 
 impl Api {
-    const API_PATH: &'static str = "api/v3";
-    const QUEUE_PARAMS: &'static str = "page=1&pageSize=1000";
-    const DELETE_PARAMS: &'static str =
-        "removeFromClient=false&blocklist=true&changeCategory=false";
+    const API_KEY_HEADER: &'static str = "X-Api-Key";
+    pub const DEFAULT_PAGE_SIZE: i64 = 1000;
+    const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+    const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+    const DEFAULT_RETRIES: u32 = 3;
+    const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+    const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+    const SEARCH_POLL_INTERVAL: Duration = Duration::from_secs(2);
+    const SEARCH_TIMEOUT: Duration = Duration::from_secs(600);
 
-    pub fn new(source_url: String, api_key: String, radarr: bool, skip_redownload: bool) -> Self {
-        Self {
+    pub fn new(
+        source_url: String,
+        api_key: String,
+        kind: Option<Kind>,
+        page_size: i64,
+        diagnose: bool,
+        include_parent: bool,
+        http: &HttpConfig,
+    ) -> Result<Self> {
+        let client = Client::builder()
+            .connect_timeout(
+                http.connect_timeout
+                    .unwrap_or(Self::DEFAULT_CONNECT_TIMEOUT),
+            )
+            .read_timeout(http.read_timeout.unwrap_or(Self::DEFAULT_READ_TIMEOUT))
+            .build()
+            .context("failed to build HTTP client")?;
+
+        Ok(Self {
             source_url,
             api_key,
-            radarr,
-            skip_redownload,
-            client: Client::new(),
+            kind,
+            arr: OnceCell::new(),
+            page_size,
+            diagnose,
+            include_parent,
+            retries: http.retries.unwrap_or(Self::DEFAULT_RETRIES),
+            retry_backoff: http.retry_backoff.unwrap_or(Self::DEFAULT_RETRY_BACKOFF),
+            client,
+        })
+    }
+
+    /// Send a request, retrying with jittered exponential backoff when `is_retryable` allows it.
+    /// `query` is encoded into the URL, for values that aren't known to be URL-safe.
+    /// Responses that aren't retried are returned for the caller to report, successful or not.
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        query: &[(&str, &str)],
+        body: Option<&serde_json::Value>,
+        action: &str,
+    ) -> Result<Response> {
+        let accept = if method == Method::GET {
+            "application/json"
+        } else {
+            "*/*"
+        };
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut request = self
+                .client
+                .request(method.clone(), url)
+                .query(query)
+                .header(ACCEPT, accept)
+                .header(Self::API_KEY_HEADER, &self.api_key);
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            let result = request.send().await;
+
+            let wait = result.as_ref().ok().and_then(retry_after);
+            let status = result.as_ref().ok().map(Response::status);
+            let retryable = is_retryable(&method, status, wait.is_some());
+            if !retryable || attempt > self.retries {
+                let attempts = if attempt > 1 {
+                    format!(" after {} attempts", attempt)
+                } else {
+                    String::new()
+                };
+                return match result {
+                    Ok(response) if retryable => bail!(
+                        "Failed to {}. Bad status code: {}{}",
+                        action,
+                        response.status(),
+                        attempts
+                    ),
+                    Ok(response) => Ok(response),
+                    Err(e) => Err(e).with_context(|| format!("failed to {}{}", action, attempts)),
+                };
+            }
+
+            let delay = wait.unwrap_or_else(|| self.backoff(attempt));
+            let cause = match &result {
+                Ok(response) => response.status().to_string(),
+                Err(e) => e.to_string(),
+            };
+            println!(
+                "Failed to {} ({}), retrying in {}",
+                action,
+                self.scrub(&cause),
+                humantime::format_duration(Duration::from_millis(delay.as_millis() as u64))
+            );
+            tokio::time::sleep(delay).await;
         }
     }
 
-    pub async fn get_queue(&self) -> Result<Box<dyn QueueJson>> {
-        let url = format!(
-            "{}/{}/queue?{}&apikey={}",
+    /// Wait before retry number `attempt`: the backoff doubled for each earlier retry,
+    /// with random jitter so instances retrying together spread out
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .retry_backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(Self::MAX_RETRY_BACKOFF);
+        backoff.mul_f64(0.5 + fastrand::f64() / 2.0)
+    }
+
+    /// Replace the API key anywhere it appears in `text`, so it never ends up in logs
+    pub fn scrub(&self, text: &str) -> String {
+        if self.api_key.is_empty() {
+            return text.to_string();
+        }
+        text.replace(&self.api_key, "<api key>")
+    }
+
+    /// Detect the application behind the instance now rather than on first use.
+    /// Fails with `Refused` if it can't be handled, however often it's tried.
+    pub async fn connect(&self) -> Result<()> {
+        self.arr().await.map(|_| ())
+    }
+
+    /// The application behind the instance, detected on first use
+    async fn arr(&self) -> Result<&'static dyn ArrKind> {
+        self.arr.get_or_try_init(|| self.detect()).await.copied()
+    }
+
+    /// Ask the instance which application and version it is, and refuse the ones that can't be handled.
+    /// If a kind was configured the instance has to match it.
+    async fn detect(&self) -> Result<&'static dyn ArrKind> {
+        let kinds = match self.kind {
+            Some(kind) => vec![kind],
+            None => Kind::value_variants().to_vec(),
+        };
+        let mut api_paths: Vec<&str> = kinds.iter().map(|kind| kind.arr().api_path()).collect();
+        api_paths.dedup();
+
+        let mut found = None;
+        for api_path in api_paths {
+            let url = format!("{}/{}/system/status", self.source_url, api_path);
+
+            let response = self
+                .send(Method::GET, &url, &[], None, "get system status")
+                .await?;
+
+            if response.status() == StatusCode::NOT_FOUND {
+                continue;
+            }
+            ensure!(
+                response.status().is_success(),
+                Refused(format!(
+                    "Failed to get system status. Bad status code: {}",
+                    response.status()
+                ))
+            );
+
+            let status = response
+                .json::<system::Status>()
+                .await
+                .context("failed to parse system status")?;
+            found = Some((api_path, status));
+            break;
+        }
+        let Some((api_path, status)) = found else {
+            bail!(Refused(format!(
+                "No system status found, {} isn't a supported application",
+                self.source_url
+            )));
+        };
+
+        let arr = check_status(&status, api_path, self.kind)?;
+
+        println!(
+            "Connected to {} {} at {}",
+            status.app_name, status.version, self.source_url
+        );
+        Ok(arr)
+    }
+
+    /// Fetch every page of the queue and merge the records.
+    /// A record that moved to a later page while fetching is only kept once.
+    pub async fn get_queue(&self) -> Result<Vec<Record>> {
+        let mut records = Vec::new();
+        let mut seen = HashSet::new();
+        for page in 1.. {
+            let queue = self.get_queue_page(page).await?;
+            let page_records = queue.get_records();
+            let done = is_last_page(page, page_records.len(), queue.get_page_count());
+            records.extend(
+                page_records
+                    .into_iter()
+                    .filter(|record| seen.insert(record.id)),
+            );
+            if done {
+                break;
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Fetch a single page of the queue, starting from 1
+    pub async fn get_queue_page(&self, page: i64) -> Result<Box<dyn QueueJson>> {
+        let arr = self.arr().await?;
+        let mut url = format!(
+            "{}/{}/queue?page={}&pageSize={}",
             self.source_url,
-            Self::API_PATH,
-            Self::QUEUE_PARAMS,
-            self.api_key
+            arr.api_path(),
+            page,
+            self.page_size
         );
+        if self.include_parent {
+            url = format!("{}&{}=true", url, arr.include_parent_param());
+        }
 
         let response = self
-            .client
-            .get(&url)
-            .header(ACCEPT, "application/json")
-            .send()
-            .await
-            .context("failed to get queue data")?;
+            .send(Method::GET, &url, &[], None, "get queue data")
+            .await?;
 
         ensure!(
             response.status().is_success(),
@@ -53,184 +319,541 @@ impl Api {
             response.status()
         );
 
-        if self.radarr {
-            Ok(Box::new(response.json::<radarr::Queue>().await?))
-        } else {
-            Ok(Box::new(response.json::<sonarr::Queue>().await?))
-        }
+        let body = response.bytes().await?;
+        let parsed = arr
+            .parse_queue(&body, self.diagnose)
+            .context("failed to parse queue data")?;
+        Ok(Self::report_deviations(
+            &format!("queue page {}", page),
+            parsed,
+        ))
     }
 
     pub async fn get_media(&self, media_id: i64) -> Result<Box<dyn MediaItem>> {
-        let path = if self.radarr { "movie" } else { "episode" };
-
+        let arr = self.arr().await?;
         let url = format!(
-            "{}/{}/{}/{}?apikey={}",
+            "{}/{}/{}",
             self.source_url,
-            Self::API_PATH,
-            path,
-            media_id,
-            self.api_key
+            arr.api_path(),
+            arr.media_path(media_id)
         );
 
-        let response = self
-            .client
-            .get(&url)
-            .header(ACCEPT, "application/json")
-            .send()
-            .await?;
+        let action = format!("get media {}", media_id);
+        let response = self.send(Method::GET, &url, &[], None, &action).await?;
 
         ensure!(
             response.status().is_success(),
-            "Failed to get media {}",
-            media_id
+            "Failed to {}. Bad status code: {}",
+            action,
+            response.status()
         );
 
-        if self.radarr {
-            Ok(Box::new(response.json::<radarr::Movie>().await?))
-        } else {
-            Ok(Box::new(response.json::<sonarr::Episode>().await?))
+        let body = response.bytes().await?;
+        let parsed = arr
+            .parse_media(&body, self.diagnose)
+            .with_context(|| format!("failed to parse media {}", media_id))?;
+        Ok(Self::report_deviations(
+            &format!("media {}", media_id),
+            parsed,
+        ))
+    }
+
+    fn report_deviations<T>(what: &str, parsed: Parsed<T>) -> T {
+        if !parsed.deviations.is_empty() {
+            println!("Response for {} deviated from the model:", what);
+            for deviation in &parsed.deviations {
+                println!("  {}", deviation);
+            }
         }
+        parsed.value
     }
 
-    pub async fn delete_queue_record(&self, record: &Record) -> Result<()> {
+    pub async fn delete_queue_record(&self, record: &Record, flags: RemovalFlags) -> Result<()> {
+        let arr = self.arr().await?;
         let url = format!(
-            "{}/{}/queue/{}?{}&skipRedownload={}&apikey={}",
+            "{}/{}/queue/{}?{}",
             self.source_url,
-            Self::API_PATH,
+            arr.api_path(),
             record.id,
-            Self::DELETE_PARAMS,
-            self.skip_redownload,
-            self.api_key
+            flags
+        );
+
+        let action = format!("delete record {}", record.title);
+        let response = self.send(Method::DELETE, &url, &[], None, &action).await?;
+
+        ensure!(
+            response.status().is_success(),
+            "Failed to {}. Bad status code: {}",
+            action,
+            response.status()
+        );
+
+        Ok(())
+    }
+
+    /// Remove several records from the queue in one request, all with the same flags
+    pub async fn delete_queue_records(
+        &self,
+        records: &[&Record],
+        flags: RemovalFlags,
+    ) -> Result<()> {
+        let arr = self.arr().await?;
+        let url = format!(
+            "{}/{}/queue/bulk?{}",
+            self.source_url,
+            arr.api_path(),
+            flags
         );
+        let ids: Vec<i64> = records.iter().map(|record| record.id).collect();
+        let body = serde_json::json!({ "ids": ids });
 
+        let action = format!("delete {} records", records.len());
         let response = self
-            .client
-            .delete(&url)
-            .header(ACCEPT, "*/*")
-            .send()
+            .send(Method::DELETE, &url, &[], Some(&body), &action)
             .await?;
 
         ensure!(
             response.status().is_success(),
-            "Failed to delete record {}. Bad status code: {}",
-            record.title,
+            "Failed to {}. Bad status code: {}",
+            action,
             response.status()
         );
 
         Ok(())
     }
 
-    pub async fn delete_episode_file(&self, record: &Record) -> Result<()> {
-        let path = if self.radarr {
-            "moviefile"
-        } else {
-            "episodefile"
-        };
+    /// Search for new releases of the media and wait for the search to finish
+    pub async fn search(&self, media_ids: &[i64]) -> Result<Command> {
+        let body = self.arr().await?.search_command(media_ids);
+        self.run_search(&body).await
+    }
 
-        let media = self.get_media(record.media_id).await?;
+    /// Search for a release of a whole season and wait for the search to finish
+    pub async fn search_season(&self, season: Season) -> Result<Command> {
+        let arr = self.arr().await?;
+        let body = arr
+            .season_search_command(season)
+            .with_context(|| format!("{} can't search for seasons", arr.app_name()))?;
+        self.run_search(&body).await
+    }
 
-        let file_id = media
-            .get_file_id()
-            .ok_or(anyhow::anyhow!("Record does not have a file"))?;
+    async fn run_search(&self, body: &serde_json::Value) -> Result<Command> {
+        let arr = self.arr().await?;
+        let url = format!("{}/{}/command", self.source_url, arr.api_path());
 
-        let url = format!(
-            "{}/{}/{}/{}?apikey={}",
-            self.source_url,
-            Self::API_PATH,
-            path,
-            file_id,
-            self.api_key,
+        let response = self
+            .send(Method::POST, &url, &[], Some(body), "start search")
+            .await?;
+        ensure!(
+            response.status().is_success(),
+            "Failed to start search. Bad status code: {}",
+            response.status()
+        );
+        let mut command: Command = response
+            .json()
+            .await
+            .context("failed to parse search command")?;
+
+        let started = Instant::now();
+        while !command.status.is_finished() {
+            ensure!(
+                started.elapsed() < Self::SEARCH_TIMEOUT,
+                "Search is still {} after {}",
+                command.status,
+                humantime::format_duration(Self::SEARCH_TIMEOUT)
+            );
+            tokio::time::sleep(Self::SEARCH_POLL_INTERVAL).await;
+
+            let url = format!(
+                "{}/{}/command/{}",
+                self.source_url,
+                arr.api_path(),
+                command.id
+            );
+            let response = self
+                .send(Method::GET, &url, &[], None, "get search status")
+                .await?;
+            ensure!(
+                response.status().is_success(),
+                "Failed to get search status. Bad status code: {}",
+                response.status()
+            );
+            command = response
+                .json()
+                .await
+                .context("failed to parse search command")?;
+        }
+
+        Ok(command)
+    }
+
+    /// Ids of the episodes of a season that have aired
+    pub async fn get_aired_episodes(&self, season: Season) -> Result<Vec<i64>> {
+        let arr = self.arr().await?;
+        let path = arr
+            .season_path(season)
+            .with_context(|| format!("{} doesn't have seasons", arr.app_name()))?;
+        let url = format!("{}/{}/{}", self.source_url, arr.api_path(), path);
+
+        let response = self
+            .send(Method::GET, &url, &[], None, "get season")
+            .await?;
+        ensure!(
+            response.status().is_success(),
+            "Failed to get season. Bad status code: {}",
+            response.status()
         );
 
+        let body = response.bytes().await?;
+        let parsed = arr
+            .parse_aired_episodes(&body, self.diagnose)
+            .context("failed to parse season")?;
+        Ok(Self::report_deviations("season", parsed))
+    }
+
+    /// Releases grabbed since `date`, an RFC 3339 timestamp
+    pub async fn get_grabs_since(&self, date: &str) -> Result<Vec<Grab>> {
+        let arr = self.arr().await?;
+        let url = format!("{}/{}/history/since", self.source_url, arr.api_path());
+        let query = [("date", date), ("eventType", "grabbed")];
+
         let response = self
-            .client
-            .delete(&url)
-            .header(ACCEPT, "*/*")
-            .send()
+            .send(Method::GET, &url, &query, None, "get grab history")
             .await?;
+        ensure!(
+            response.status().is_success(),
+            "Failed to get grab history. Bad status code: {}",
+            response.status()
+        );
 
+        let body = response.bytes().await?;
+        let parsed = arr
+            .parse_grabs(&body, self.diagnose)
+            .context("failed to parse grab history")?;
+        Ok(Self::report_deviations("grab history", parsed))
+    }
+
+    /// Labels of every tag, by id
+    pub async fn get_tags(&self) -> Result<HashMap<i64, String>> {
+        let arr = self.arr().await?;
+        let url = format!("{}/{}/tag", self.source_url, arr.api_path());
+
+        let response = self.send(Method::GET, &url, &[], None, "get tags").await?;
         ensure!(
             response.status().is_success(),
-            "Failed to delete file for record {}. Bad status code: {}",
-            record.title,
+            "Failed to get tags. Bad status code: {}",
             response.status()
         );
 
+        let body = response.bytes().await?;
+        let parsed =
+            json::parse::<Vec<Tag>>(&body, self.diagnose).context("failed to parse tags")?;
+        let tags = Self::report_deviations("tags", parsed);
+        Ok(tags.into_iter().map(|tag| (tag.id, tag.label)).collect())
+    }
+
+    /// Look up the files currently attached to the record's media.
+    /// They're all kept if any of them isn't from the record's release, since that's a file worth keeping.
+    pub async fn get_files(&self, record: &Record) -> Result<Files> {
+        let media = self.get_media(record.media_id).await?;
+        let files = media.get_files();
+
+        if files.is_empty() {
+            return Ok(Files::NoFile);
+        }
+        let mut file_ids = BTreeSet::new();
+        for file in &files {
+            let Some(file_id) = file.id else {
+                return Ok(Files::Keep("a file has no id".to_string()));
+            };
+            if let Some(mismatch) = file.mismatch(record) {
+                return Ok(Files::Keep(format!("file {}: {}", file_id, mismatch)));
+            }
+            file_ids.insert(file_id);
+        }
+        Ok(Files::Delete(file_ids))
+    }
+
+    /// Look up the files of every record of a download, once each even when records share a file.
+    /// They're all kept if any record's would be.
+    pub async fn get_download_files(&self, records: &[&Record]) -> Result<Files> {
+        let mut file_ids = BTreeSet::new();
+        for record in records {
+            match self.get_files(record).await? {
+                Files::Delete(ids) => file_ids.extend(ids),
+                Files::NoFile => {}
+                keep @ Files::Keep(_) => return Ok(keep),
+            }
+        }
+        Ok(if file_ids.is_empty() {
+            Files::NoFile
+        } else {
+            Files::Delete(file_ids)
+        })
+    }
+
+    /// Delete files of a record's media
+    pub async fn delete_files(&self, record: &Record, file_ids: &BTreeSet<i64>) -> Result<()> {
+        let arr = self.arr().await?;
+        for file_id in file_ids {
+            let url = format!(
+                "{}/{}/{}",
+                self.source_url,
+                arr.api_path(),
+                arr.file_path(*file_id)
+            );
+
+            let action = format!("delete file {} for record {}", file_id, record.title);
+            let response = self.send(Method::DELETE, &url, &[], None, &action).await?;
+
+            ensure!(
+                response.status().is_success(),
+                "Failed to {}. Bad status code: {}",
+                action,
+                response.status()
+            );
+        }
+
         Ok(())
     }
 }
 
-pub trait QueueJson {
-    fn get_records(&self) -> Vec<Record>;
+/// The files of a record's media, and whether they can be deleted
+#[derive(Debug, PartialEq, Eq)]
+pub enum Files {
+    /// Ids of the files, all from the failed release
+    Delete(BTreeSet<i64>),
+    /// The media has no file
+    NoFile,
+    /// Why the files are kept: one of them isn't known to be from the failed release
+    Keep(String),
 }
 
-impl QueueJson for sonarr::Queue {
-    fn get_records(&self) -> Vec<Record> {
-        self.records.iter().map(Record::from).collect()
+/// An instance that can't be checked however often it's retried, like an unsupported application or version
+#[derive(Debug)]
+pub struct Refused(pub String);
+
+impl Refused {
+    /// Whether `error` was caused by a refusal
+    pub fn caused(error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| cause.is::<Refused>())
     }
 }
 
-impl QueueJson for radarr::Queue {
-    fn get_records(&self) -> Vec<Record> {
-        self.records.iter().map(Record::from).collect()
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
-pub trait MediaItem {
-    fn get_file_id(&self) -> Option<i64>;
-}
+impl std::error::Error for Refused {}
 
-impl MediaItem for sonarr::Episode {
-    fn get_file_id(&self) -> Option<i64> {
-        self.episode_file_id
+/// The application an instance reported in its system status, refused if it can't be handled.
+/// `api_path` is where the status was found, and `expected` the kind the instance was configured as.
+fn check_status(
+    status: &system::Status,
+    api_path: &str,
+    expected: Option<Kind>,
+) -> Result<&'static dyn ArrKind> {
+    let kind = Kind::from_app_name(&status.app_name).ok_or_else(|| {
+        Refused(format!(
+            "{} {} is not supported",
+            status.app_name, status.version
+        ))
+    })?;
+    let arr = kind.arr();
+    if let Some(expected) = expected {
+        ensure!(
+            kind == expected,
+            Refused(format!(
+                "Instance is {} {}, but was configured as {}",
+                status.app_name,
+                status.version,
+                expected.arr().app_name()
+            ))
+        );
     }
+
+    let major = status
+        .version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u64>().ok());
+    ensure!(
+        major.is_some_and(|major| major >= arr.min_version()) && api_path == arr.api_path(),
+        Refused(format!(
+            "{} {} is not supported, version {} or newer with {} is needed",
+            status.app_name,
+            status.version,
+            arr.min_version(),
+            arr.api_path()
+        ))
+    );
+    Ok(arr)
 }
 
-impl MediaItem for radarr::Movie {
-    fn get_file_id(&self) -> Option<i64> {
-        self.movie_file_id
+/// Whether a request can be sent again after failing with `status`, or without a response if None.
+/// GETs are always safe to repeat. Other requests may have been processed even though they failed,
+/// like a search queued twice or a deleted record then missing, so they're only repeated when the server
+/// clearly turned them down: a 429, or a 503 asking to retry later.
+fn is_retryable(method: &Method, status: Option<StatusCode>, has_retry_after: bool) -> bool {
+    if method == Method::GET {
+        return status.is_none_or(|status| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        });
+    }
+    match status {
+        Some(StatusCode::TOO_MANY_REQUESTS) => true,
+        Some(StatusCode::SERVICE_UNAVAILABLE) => has_retry_after,
+        _ => false,
     }
 }
 
+/// Whether the queue walk stops after `page`: it came back empty, or the queue has no pages after it
+fn is_last_page(page: i64, records: usize, page_count: i64) -> bool {
+    records == 0 || page >= page_count
+}
+
+/// Wait asked for by a 429 or 503 response, when given in seconds
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    let delay = Duration::from_secs(seconds.trim().parse().ok()?);
+    Some(delay.min(Api::MAX_RETRY_BACKOFF))
+}
+
 #[derive(Debug)]
 pub struct Record {
-    id: i64,
-    media_id: i64,
-    title: String,
-    tracked_status: String,
-    status: String,
+    pub id: i64,
+    pub media_id: i64,
+    pub title: String,
+    pub download_id: String,
+    pub status: QueueStatus,
+    pub tracked_status: TrackedDownloadStatus,
+    pub tracked_state: TrackedDownloadState,
+    pub status_messages: Vec<String>,
+    pub error_message: Option<String>,
+    pub protocol: DownloadProtocol,
+    pub indexer: String,
+    pub download_client: String,
+    /// Whether the download client moves downloads to another category after import
+    pub has_post_import_category: Option<bool>,
+    pub quality: Option<String>,
+    pub custom_format_score: Option<i64>,
+    pub size: i64,
+    pub sizeleft: i64,
+    pub timeleft: Option<String>,
+    pub estimated_completion_time: Option<String>,
+    pub added: String,
+    pub reason: FailureReason,
+    /// The season the record's episode is in, only for Sonarr
+    pub season: Option<Season>,
+    pub parent: Parent,
 }
 
-impl Record {
-    pub fn get_tracked_status(&self) -> &str {
-        &self.tracked_status
-    }
+/// The series, movie, artist or author a record is for.
+/// Everything but the id is only known when the queue was fetched with it.
+#[derive(Debug, Default)]
+pub struct Parent {
+    pub id: Option<i64>,
+    pub title: Option<String>,
+    pub tags: Vec<i64>,
+    pub quality_profile_id: Option<i64>,
+}
 
-    pub fn get_status(&self) -> &str {
-        &self.status
+/// A release grabbed for a media item
+#[derive(Debug)]
+pub struct Grab {
+    pub media_id: i64,
+    pub title: String,
+    pub download_id: String,
+}
+
+/// A file attached to a media item, with what's known of the release it was imported from
+#[derive(Debug)]
+pub struct MediaFile {
+    /// None when the API left it out, so the file can't be deleted
+    pub id: Option<i64>,
+    pub scene_name: Option<String>,
+    pub quality: Option<String>,
+    pub custom_format_score: Option<i64>,
+}
+
+impl MediaFile {
+    /// Why the file isn't from the record's release, or None if it is.
+    /// Only a scene name equal to the release's title proves the file came from it, quality and
+    /// custom format score can only show it didn't.
+    pub fn mismatch(&self, record: &Record) -> Option<String> {
+        let Some(scene_name) = &self.scene_name else {
+            return Some("the release it was imported from isn't known".to_string());
+        };
+        if !scene_name.eq_ignore_ascii_case(&record.title) {
+            return Some(format!("it was imported from {}", scene_name));
+        }
+        if let (Some(file), Some(release)) = (&self.quality, &record.quality) {
+            if !file.eq_ignore_ascii_case(release) {
+                return Some(format!("its quality is {} instead of {}", file, release));
+            }
+        }
+        if let (Some(file), Some(release)) = (self.custom_format_score, record.custom_format_score)
+        {
+            if file != release {
+                return Some(format!(
+                    "its custom format score is {} instead of {}",
+                    file, release
+                ));
+            }
+        }
+        None
     }
 }
 
-impl From<&sonarr::Record> for Record {
-    fn from(record: &sonarr::Record) -> Self {
-        Self {
-            id: record.id,
-            media_id: record.episode_id,
-            title: record.title.clone(),
-            tracked_status: record.tracked_download_status.clone(),
-            status: record.status.clone(),
+/// A season of a series
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Season {
+    pub series_id: i64,
+    pub season_number: i64,
+}
+
+impl Record {
+    /// Identifies the record's download. Records without a download id, like pending or delayed
+    /// ones, each count as a download of their own.
+    pub fn download_key(&self) -> String {
+        if self.download_id.is_empty() {
+            format!("record:{}", self.id)
+        } else {
+            self.download_id.clone()
         }
     }
 }
 
-impl From<&radarr::Record> for Record {
-    fn from(record: &radarr::Record) -> Self {
+#[cfg(test)]
+impl Record {
+    /// A record with only its ids set, for tests to fill in
+    pub fn example(id: i64, download_id: &str) -> Self {
         Self {
-            id: record.id,
-            media_id: record.movie_id,
-            title: record.title.clone(),
-            tracked_status: record.tracked_download_status.clone(),
-            status: record.status.clone(),
+            id,
+            media_id: id,
+            title: format!("Release {}", id),
+            download_id: download_id.to_string(),
+            status: QueueStatus::default(),
+            tracked_status: TrackedDownloadStatus::default(),
+            tracked_state: TrackedDownloadState::default(),
+            status_messages: Vec::new(),
+            error_message: None,
+            protocol: DownloadProtocol::default(),
+            indexer: String::new(),
+            download_client: String::new(),
+            has_post_import_category: None,
+            quality: None,
+            custom_format_score: None,
+            size: 0,
+            sizeleft: 0,
+            timeleft: None,
+            estimated_completion_time: None,
+            added: String::new(),
+            reason: FailureReason::NoMessage,
+            season: None,
+            parent: Parent::default(),
         }
     }
 }
@@ -240,3 +863,262 @@ impl fmt::Display for Record {
         write!(f, "{}: {}", self.id, self.title)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(scene_name: Option<&str>, quality: Option<&str>, score: Option<i64>) -> MediaFile {
+        MediaFile {
+            id: Some(1),
+            scene_name: scene_name.map(str::to_string),
+            quality: quality.map(str::to_string),
+            custom_format_score: score,
+        }
+    }
+
+    fn release() -> Record {
+        let mut record = Record::example(1, "a");
+        record.title = "Show.S01E01.1080p.WEB-DL".to_string();
+        record.quality = Some("WEBDL-1080p".to_string());
+        record.custom_format_score = Some(100);
+        record
+    }
+
+    #[test]
+    fn file_from_the_release_matches() {
+        let file = file(
+            Some("show.s01e01.1080p.web-dl"),
+            Some("WEBDL-1080p"),
+            Some(100),
+        );
+        assert_eq!(file.mismatch(&release()), None);
+    }
+
+    fn status(app_name: &str, version: &str) -> system::Status {
+        system::Status {
+            app_name: app_name.to_string(),
+            version: version.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The app name of a supported status, or the refusal of an unsupported one
+    fn check(app_name: &str, version: &str, api_path: &str, expected: Option<Kind>) -> String {
+        match check_status(&status(app_name, version), api_path, expected) {
+            Ok(arr) => arr.app_name().to_string(),
+            Err(e) => {
+                assert!(Refused::caused(&e), "{:?}", e);
+                e.to_string()
+            }
+        }
+    }
+
+    #[test]
+    fn accepts_supported_applications() {
+        assert_eq!(check("Sonarr", "4.0.5.1710", "api/v3", None), "Sonarr");
+        assert_eq!(check("sonarr", "3.0.10.1567", "api/v3", None), "Sonarr");
+        assert_eq!(check("Radarr", "5.3.6.8612", "api/v3", None), "Radarr");
+        assert_eq!(check("Lidarr", "2.1.7.4030", "api/v1", None), "Lidarr");
+        assert_eq!(check("Readarr", "0.3.18.2411", "api/v1", None), "Readarr");
+        assert_eq!(
+            check("Radarr", "5.3.6.8612", "api/v3", Some(Kind::Radarr)),
+            "Radarr"
+        );
+    }
+
+    #[test]
+    fn refuses_unknown_applications() {
+        assert_eq!(
+            check("Whisparr", "2.0.0.548", "api/v3", None),
+            "Whisparr 2.0.0.548 is not supported"
+        );
+    }
+
+    #[test]
+    fn refuses_a_different_kind_than_configured() {
+        assert_eq!(
+            check("Radarr", "5.3.6.8612", "api/v3", Some(Kind::Sonarr)),
+            "Instance is Radarr 5.3.6.8612, but was configured as Sonarr"
+        );
+    }
+
+    #[test]
+    fn refuses_old_or_unknown_versions() {
+        assert_eq!(
+            check("Sonarr", "2.0.0.5344", "api/v3", None),
+            "Sonarr 2.0.0.5344 is not supported, version 3 or newer with api/v3 is needed"
+        );
+        assert_eq!(
+            check("Lidarr", "0.8.1.2135", "api/v1", None),
+            "Lidarr 0.8.1.2135 is not supported, version 1 or newer with api/v1 is needed"
+        );
+        assert_eq!(
+            check("Sonarr", "", "api/v3", None),
+            "Sonarr  is not supported, version 3 or newer with api/v3 is needed"
+        );
+        // Found at another application's API path
+        assert_eq!(
+            check("Radarr", "5.3.6.8612", "api/v1", None),
+            "Radarr 5.3.6.8612 is not supported, version 3 or newer with api/v3 is needed"
+        );
+    }
+
+    #[test]
+    fn retries_failed_gets() {
+        let get = Method::GET;
+        assert!(is_retryable(&get, None, false));
+        assert!(is_retryable(
+            &get,
+            Some(StatusCode::INTERNAL_SERVER_ERROR),
+            false
+        ));
+        assert!(is_retryable(
+            &get,
+            Some(StatusCode::TOO_MANY_REQUESTS),
+            false
+        ));
+        assert!(!is_retryable(&get, Some(StatusCode::OK), false));
+        assert!(!is_retryable(&get, Some(StatusCode::NOT_FOUND), false));
+    }
+
+    #[test]
+    fn retries_other_requests_only_when_turned_down() {
+        for method in [Method::POST, Method::DELETE, Method::PUT] {
+            assert!(!is_retryable(&method, None, false));
+            assert!(!is_retryable(
+                &method,
+                Some(StatusCode::INTERNAL_SERVER_ERROR),
+                false
+            ));
+            assert!(!is_retryable(&method, Some(StatusCode::BAD_GATEWAY), true));
+            assert!(!is_retryable(
+                &method,
+                Some(StatusCode::SERVICE_UNAVAILABLE),
+                false
+            ));
+            assert!(is_retryable(
+                &method,
+                Some(StatusCode::SERVICE_UNAVAILABLE),
+                true
+            ));
+            assert!(is_retryable(
+                &method,
+                Some(StatusCode::TOO_MANY_REQUESTS),
+                false
+            ));
+        }
+    }
+
+    fn queue(total_records: i64, page_size: i64) -> json::sonarr::Queue {
+        json::sonarr::Queue {
+            total_records,
+            page_size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn counts_pages_holding_every_record() {
+        assert_eq!(queue(0, 1000).get_page_count(), 0);
+        assert_eq!(queue(1, 1000).get_page_count(), 1);
+        assert_eq!(queue(1000, 1000).get_page_count(), 1);
+        assert_eq!(queue(1001, 1000).get_page_count(), 2);
+        assert_eq!(queue(25, 10).get_page_count(), 3);
+        // A page size the server left out doesn't divide by zero
+        assert_eq!(queue(3, 0).get_page_count(), 3);
+    }
+
+    #[test]
+    fn stops_on_the_last_or_an_empty_page() {
+        assert!(!is_last_page(1, 10, 3));
+        assert!(!is_last_page(2, 10, 3));
+        assert!(is_last_page(3, 5, 3));
+        // Records removed while walking can leave fewer pages than first counted
+        assert!(is_last_page(3, 10, 2));
+        assert!(is_last_page(2, 0, 3));
+        assert!(is_last_page(1, 0, 0));
+    }
+
+    fn with_key(api_key: &str, http: &HttpConfig) -> Api {
+        Api::new(
+            "http://localhost:8989".to_string(),
+            api_key.to_string(),
+            None,
+            Api::DEFAULT_PAGE_SIZE,
+            false,
+            false,
+            http,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn scrubs_the_api_key() {
+        let api = with_key("0123456789abcdef", &HttpConfig::default());
+        assert_eq!(
+            api.scrub("error sending request for url (http://localhost:8989/api/v3/queue?apikey=0123456789abcdef): 0123456789abcdef"),
+            "error sending request for url (http://localhost:8989/api/v3/queue?apikey=<api key>): <api key>"
+        );
+        assert_eq!(api.scrub("nothing secret"), "nothing secret");
+
+        // An empty key would otherwise be replaced between every character
+        assert_eq!(
+            with_key("", &HttpConfig::default()).scrub("nothing secret"),
+            "nothing secret"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let api = with_key(
+            "",
+            &HttpConfig {
+                retry_backoff: Some(Duration::from_secs(1)),
+                ..Default::default()
+            },
+        );
+        for (attempt, full) in [(1, 1), (2, 2), (3, 4), (4, 8), (10, 30), (40, 30)] {
+            let full = Duration::from_secs(full);
+            let delay = api.backoff(attempt);
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn compares_only_what_is_known() {
+        let mut unscored = release();
+        unscored.custom_format_score = None;
+        assert_eq!(
+            file(Some("Show.S01E01.1080p.WEB-DL"), None, Some(0)).mismatch(&unscored),
+            None
+        );
+    }
+
+    #[test]
+    fn unknown_scene_name_is_a_mismatch() {
+        // The existing file of a "not an upgrade" grab usually has the release's quality
+        let unknown = Some("the release it was imported from isn't known".to_string());
+        assert_eq!(
+            file(None, Some("WEBDL-1080p"), Some(100)).mismatch(&release()),
+            unknown
+        );
+        assert_eq!(file(None, None, None).mismatch(&release()), unknown);
+    }
+
+    #[test]
+    fn other_release_is_a_mismatch() {
+        assert_eq!(
+            file(Some("Show.S01E01.2160p.WEB-DL"), None, None).mismatch(&release()),
+            Some("it was imported from Show.S01E01.2160p.WEB-DL".to_string())
+        );
+        assert_eq!(
+            file(Some("Show.S01E01.1080p.WEB-DL"), Some("Bluray-1080p"), None).mismatch(&release()),
+            Some("its quality is Bluray-1080p instead of WEBDL-1080p".to_string())
+        );
+        assert_eq!(
+            file(Some("Show.S01E01.1080p.WEB-DL"), None, Some(50)).mismatch(&release()),
+            Some("its custom format score is 50 instead of 100".to_string())
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use serde_json::json;
+
+use super::{non_empty, ArrKind, MediaItem, QueueJson};
+use crate::{
+    api::{Grab, MediaFile, Record},
+    json::{self, lidarr, Parsed},
+};
+
+pub struct Lidarr;
+
+impl ArrKind for Lidarr {
+    fn app_name(&self) -> &'static str {
+        "Lidarr"
+    }
+
+    fn min_version(&self) -> u64 {
+        1
+    }
+
+    fn api_path(&self) -> &'static str {
+        "api/v1"
+    }
+
+    fn media_path(&self, media_id: i64) -> String {
+        // Albums don't list their files, so ask for the album's track files directly
+        format!("trackfile?albumId={}", media_id)
+    }
+
+    fn file_path(&self, file_id: i64) -> String {
+        format!("trackfile/{}", file_id)
+    }
+
+    fn include_parent_param(&self) -> &'static str {
+        "includeArtist"
+    }
+
+    fn search_command(&self, media_ids: &[i64]) -> serde_json::Value {
+        json!({ "name": "AlbumSearch", "albumIds": media_ids })
+    }
+
+    fn parse_queue(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn QueueJson>>> {
+        let parsed = json::parse::<lidarr::Queue>(body, diagnose)?;
+        Ok(parsed.map(|queue| Box::new(queue) as Box<dyn QueueJson>))
+    }
+
+    fn parse_media(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn MediaItem>>> {
+        let parsed = json::parse::<Vec<lidarr::TrackFile>>(body, diagnose)?;
+        Ok(parsed.map(|media| Box::new(media) as Box<dyn MediaItem>))
+    }
+
+    fn parse_grabs(&self, body: &[u8], diagnose: bool) -> serde_json::Result<Parsed<Vec<Grab>>> {
+        let parsed = json::parse::<Vec<lidarr::HistoryRecord>>(body, diagnose)?;
+        Ok(parsed.map(|history| {
+            history
+                .iter()
+                .filter_map(|grab| {
+                    Some(Grab {
+                        media_id: grab.album_id?,
+                        title: grab.source_title.clone(),
+                        download_id: grab.download_id.clone().unwrap_or_default(),
+                    })
+                })
+                .collect()
+        }))
+    }
+}
+
+impl QueueJson for lidarr::Queue {
+    fn try_records(&self) -> Vec<Result<Record, String>> {
+        self.records.iter().map(Record::try_from).collect()
+    }
+
+    fn get_total_records(&self) -> i64 {
+        self.total_records
+    }
+
+    fn get_page_size(&self) -> i64 {
+        self.page_size
+    }
+}
+
+impl MediaItem for Vec<lidarr::TrackFile> {
+    fn get_files(&self) -> Vec<MediaFile> {
+        self.iter()
+            .map(|track_file| MediaFile {
+                id: track_file.id,
+                scene_name: track_file.scene_name.as_deref().and_then(non_empty),
+                quality: track_file
+                    .quality
+                    .as_ref()
+                    .map(|quality| quality.quality.name.clone()),
+                custom_format_score: track_file.custom_format_score,
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<&lidarr::Record> for Record {
+    type Error = String;
+
+    fn try_from(record: &lidarr::Record) -> Result<Self, String> {
+        queue_record!(
+            record,
+            media: album_id,
+            parent: artist_id => artist.artist_name,
+            season: None,
+        )
+    }
+}
@@ -0,0 +1,217 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{
+    api::{Grab, MediaFile, Record, Season},
+    json::Parsed,
+};
+
+/// Build a `Record` from any app's queue record, which all share these fields.
+/// Each backend only names what differs: its media id, and the id and title of the series, movie,
+/// artist or author with the optional object the queue includes it as, plus the season if it has one.
+/// Returns early with why the record can't be used when it's missing its own id or its media id.
+macro_rules! queue_record {
+    (
+        $record:ident,
+        media: $media_id:ident,
+        parent: $parent_id:ident => $parent:ident.$title:ident,
+        season: $season:expr $(,)?
+    ) => {{
+        let Some(id) = $record.id else {
+            return Err(format!("{} has no id", $record.title));
+        };
+        let Some(media_id) = $record.$media_id else {
+            return Err(format!(
+                "{} has no {}",
+                $record.title,
+                stringify!($media_id)
+            ));
+        };
+        let status_messages = $crate::arr::flatten_messages(
+            $record
+                .status_messages
+                .iter()
+                .map(|message| (&message.title, &message.messages)),
+        );
+
+        Ok($crate::api::Record {
+            id,
+            media_id,
+            title: $record.title.clone(),
+            download_id: $record.download_id.clone(),
+            status: $record.status.clone(),
+            tracked_status: $record.tracked_download_status.clone(),
+            tracked_state: $record.tracked_download_state.clone(),
+            error_message: $record.error_message.clone(),
+            protocol: $record.protocol.clone(),
+            indexer: $record.indexer.clone(),
+            download_client: $record.download_client.clone(),
+            has_post_import_category: $record.download_client_has_post_import_category,
+            quality: $record
+                .quality
+                .as_ref()
+                .map(|quality| quality.quality.name.clone()),
+            custom_format_score: $record.custom_format_score,
+            size: $record.size,
+            sizeleft: $record.sizeleft,
+            timeleft: $record.timeleft.as_deref().and_then($crate::arr::non_empty),
+            estimated_completion_time: $record
+                .estimated_completion_time
+                .as_deref()
+                .and_then($crate::arr::non_empty),
+            added: $record.added.clone().unwrap_or_default(),
+            reason: $crate::reason::FailureReason::classify(
+                &status_messages,
+                $record.error_message.as_deref(),
+            ),
+            status_messages,
+            season: $season,
+            parent: match &$record.$parent {
+                Some(parent) => $crate::api::Parent {
+                    id: $record.$parent_id,
+                    title: Some(parent.$title.clone()),
+                    tags: parent.tags.clone(),
+                    quality_profile_id: Some(parent.quality_profile_id),
+                },
+                None => $crate::api::Parent {
+                    id: $record.$parent_id,
+                    ..Default::default()
+                },
+            },
+        })
+    }};
+}
+
+mod lidarr;
+mod radarr;
+mod readarr;
+mod sonarr;
+
+/// Type of *arr instance. A new backend needs a variant here and a module implementing [`ArrKind`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    #[default]
+    Sonarr,
+    Radarr,
+    Lidarr,
+    Readarr,
+}
+
+impl Kind {
+    /// Find the kind of instance from the `appName` it reports
+    pub fn from_app_name(app_name: &str) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .copied()
+            .find(|kind| kind.arr().app_name().eq_ignore_ascii_case(app_name))
+    }
+
+    pub fn arr(self) -> &'static dyn ArrKind {
+        match self {
+            Kind::Sonarr => &sonarr::Sonarr,
+            Kind::Radarr => &radarr::Radarr,
+            Kind::Lidarr => &lidarr::Lidarr,
+            Kind::Readarr => &readarr::Readarr,
+        }
+    }
+}
+
+/// Everything that differs between *arr applications: where their API lives and what it returns
+pub trait ArrKind: Send + Sync {
+    /// Name reported as `appName` by the system status endpoint
+    fn app_name(&self) -> &'static str;
+    /// Oldest major version whose API is understood
+    fn min_version(&self) -> u64;
+    /// API path prefix -- Ex: api/v3
+    fn api_path(&self) -> &'static str;
+    /// Path to the media a queue record is for, which lists the files it has
+    fn media_path(&self, media_id: i64) -> String;
+    /// Path to a single media file, used to delete it
+    fn file_path(&self, file_id: i64) -> String;
+    /// Query parameter that includes each queue record's series, movie, artist or author
+    fn include_parent_param(&self) -> &'static str;
+    /// Command that searches for releases of the media
+    fn search_command(&self, media_ids: &[i64]) -> serde_json::Value;
+    /// Command that searches for a release of a whole season, for kinds that have seasons
+    fn season_search_command(&self, _season: Season) -> Option<serde_json::Value> {
+        None
+    }
+    /// Path listing the episodes of a season, for kinds that have seasons
+    fn season_path(&self, _season: Season) -> Option<String> {
+        None
+    }
+    /// Parse the episodes of a season into the ids of the ones that have aired
+    fn parse_aired_episodes(
+        &self,
+        _body: &[u8],
+        _diagnose: bool,
+    ) -> serde_json::Result<Parsed<Vec<i64>>> {
+        Ok(Parsed {
+            value: Vec::new(),
+            deviations: Vec::new(),
+        })
+    }
+    /// Parse a page of the queue, listing where it deviated from the model when `diagnose` is set
+    fn parse_queue(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn QueueJson>>>;
+    fn parse_media(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn MediaItem>>>;
+    /// Parse grab events from the history
+    fn parse_grabs(&self, body: &[u8], diagnose: bool) -> serde_json::Result<Parsed<Vec<Grab>>>;
+}
+
+pub trait QueueJson {
+    /// Every record of the page, or why it can't be used
+    fn try_records(&self) -> Vec<Result<Record, String>>;
+    fn get_total_records(&self) -> i64;
+    fn get_page_size(&self) -> i64;
+
+    /// The records of the page that can be acted on, reporting the ones skipped
+    fn get_records(&self) -> Vec<Record> {
+        self.try_records()
+            .into_iter()
+            .filter_map(|record| {
+                record
+                    .map_err(|reason| println!("Skipped queue record: {}", reason))
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Number of pages needed to hold every record
+    fn get_page_count(&self) -> i64 {
+        let page_size = self.get_page_size().max(1);
+        (self.get_total_records() + page_size - 1) / page_size
+    }
+}
+
+pub trait MediaItem {
+    fn get_files(&self) -> Vec<MediaFile>;
+}
+
+/// Flatten status messages into their message texts.
+/// The title is usually a file name, so it's only used when a message has no details.
+fn flatten_messages<'a>(
+    messages: impl Iterator<Item = (&'a String, &'a Vec<String>)>,
+) -> Vec<String> {
+    messages
+        .flat_map(|(title, messages)| {
+            if messages.is_empty() {
+                vec![title.clone()]
+            } else {
+                messages.clone()
+            }
+        })
+        .collect()
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
@@ -0,0 +1,119 @@
+use serde_json::json;
+
+use super::{non_empty, ArrKind, MediaItem, QueueJson};
+use crate::{
+    api::{Grab, MediaFile, Record},
+    json::{self, radarr, Parsed},
+};
+
+pub struct Radarr;
+
+impl ArrKind for Radarr {
+    fn app_name(&self) -> &'static str {
+        "Radarr"
+    }
+
+    fn min_version(&self) -> u64 {
+        3
+    }
+
+    fn api_path(&self) -> &'static str {
+        "api/v3"
+    }
+
+    fn media_path(&self, media_id: i64) -> String {
+        format!("movie/{}", media_id)
+    }
+
+    fn file_path(&self, file_id: i64) -> String {
+        format!("moviefile/{}", file_id)
+    }
+
+    fn include_parent_param(&self) -> &'static str {
+        "includeMovie"
+    }
+
+    fn search_command(&self, media_ids: &[i64]) -> serde_json::Value {
+        json!({ "name": "MoviesSearch", "movieIds": media_ids })
+    }
+
+    fn parse_queue(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn QueueJson>>> {
+        let parsed = json::parse::<radarr::Queue>(body, diagnose)?;
+        Ok(parsed.map(|queue| Box::new(queue) as Box<dyn QueueJson>))
+    }
+
+    fn parse_media(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn MediaItem>>> {
+        let parsed = json::parse::<radarr::Movie>(body, diagnose)?;
+        Ok(parsed.map(|media| Box::new(media) as Box<dyn MediaItem>))
+    }
+
+    fn parse_grabs(&self, body: &[u8], diagnose: bool) -> serde_json::Result<Parsed<Vec<Grab>>> {
+        let parsed = json::parse::<Vec<radarr::HistoryRecord>>(body, diagnose)?;
+        Ok(parsed.map(|history| {
+            history
+                .iter()
+                .filter_map(|grab| {
+                    Some(Grab {
+                        media_id: grab.movie_id?,
+                        title: grab.source_title.clone(),
+                        download_id: grab.download_id.clone().unwrap_or_default(),
+                    })
+                })
+                .collect()
+        }))
+    }
+}
+
+impl QueueJson for radarr::Queue {
+    fn try_records(&self) -> Vec<Result<Record, String>> {
+        self.records.iter().map(Record::try_from).collect()
+    }
+
+    fn get_total_records(&self) -> i64 {
+        self.total_records
+    }
+
+    fn get_page_size(&self) -> i64 {
+        self.page_size
+    }
+}
+
+impl MediaItem for radarr::Movie {
+    fn get_files(&self) -> Vec<MediaFile> {
+        let Some(id) = self.movie_file_id else {
+            return Vec::new();
+        };
+        let file = self.movie_file.as_ref();
+        vec![MediaFile {
+            id: Some(id),
+            scene_name: file
+                .and_then(|file| file.scene_name.as_deref())
+                .and_then(non_empty),
+            quality: file
+                .and_then(|file| file.quality.as_ref())
+                .map(|quality| quality.quality.name.clone()),
+            custom_format_score: file.and_then(|file| file.custom_format_score),
+        }]
+    }
+}
+
+impl TryFrom<&radarr::Record> for Record {
+    type Error = String;
+
+    fn try_from(record: &radarr::Record) -> Result<Self, String> {
+        queue_record!(
+            record,
+            media: movie_id,
+            parent: movie_id => movie.title,
+            season: None,
+        )
+    }
+}
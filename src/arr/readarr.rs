@@ -0,0 +1,118 @@
+use serde_json::json;
+
+use super::{non_empty, ArrKind, MediaItem, QueueJson};
+use crate::{
+    api::{Grab, MediaFile, Record},
+    json::{self, readarr, Parsed},
+};
+
+pub struct Readarr;
+
+impl ArrKind for Readarr {
+    fn app_name(&self) -> &'static str {
+        "Readarr"
+    }
+
+    fn min_version(&self) -> u64 {
+        0
+    }
+
+    fn api_path(&self) -> &'static str {
+        "api/v1"
+    }
+
+    fn media_path(&self, media_id: i64) -> String {
+        // Books don't list their files, so ask for the book's files directly
+        format!("bookfile?bookId={}", media_id)
+    }
+
+    fn file_path(&self, file_id: i64) -> String {
+        format!("bookfile/{}", file_id)
+    }
+
+    fn include_parent_param(&self) -> &'static str {
+        "includeAuthor"
+    }
+
+    fn search_command(&self, media_ids: &[i64]) -> serde_json::Value {
+        json!({ "name": "BookSearch", "bookIds": media_ids })
+    }
+
+    fn parse_queue(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn QueueJson>>> {
+        let parsed = json::parse::<readarr::Queue>(body, diagnose)?;
+        Ok(parsed.map(|queue| Box::new(queue) as Box<dyn QueueJson>))
+    }
+
+    fn parse_media(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn MediaItem>>> {
+        let parsed = json::parse::<Vec<readarr::BookFile>>(body, diagnose)?;
+        Ok(parsed.map(|media| Box::new(media) as Box<dyn MediaItem>))
+    }
+
+    fn parse_grabs(&self, body: &[u8], diagnose: bool) -> serde_json::Result<Parsed<Vec<Grab>>> {
+        let parsed = json::parse::<Vec<readarr::HistoryRecord>>(body, diagnose)?;
+        Ok(parsed.map(|history| {
+            history
+                .iter()
+                .filter_map(|grab| {
+                    Some(Grab {
+                        media_id: grab.book_id?,
+                        title: grab.source_title.clone(),
+                        download_id: grab.download_id.clone().unwrap_or_default(),
+                    })
+                })
+                .collect()
+        }))
+    }
+}
+
+impl QueueJson for readarr::Queue {
+    fn try_records(&self) -> Vec<Result<Record, String>> {
+        self.records.iter().map(Record::try_from).collect()
+    }
+
+    fn get_total_records(&self) -> i64 {
+        self.total_records
+    }
+
+    fn get_page_size(&self) -> i64 {
+        self.page_size
+    }
+}
+
+impl MediaItem for Vec<readarr::BookFile> {
+    fn get_files(&self) -> Vec<MediaFile> {
+        self.iter()
+            .map(|book_file| MediaFile {
+                id: book_file.id,
+                scene_name: book_file.scene_name.as_deref().and_then(non_empty),
+                quality: book_file
+                    .quality
+                    .as_ref()
+                    .map(|quality| quality.quality.name.clone()),
+                // Book files don't have custom format scores
+                custom_format_score: None,
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<&readarr::Record> for Record {
+    type Error = String;
+
+    fn try_from(record: &readarr::Record) -> Result<Self, String> {
+        queue_record!(
+            record,
+            media: book_id,
+            parent: author_id => author.author_name,
+            season: None,
+        )
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use super::{non_empty, ArrKind, MediaItem, QueueJson};
+use crate::{
+    api::{Grab, MediaFile, Record, Season},
+    json::{self, sonarr, Parsed},
+};
+
+pub struct Sonarr;
+
+impl ArrKind for Sonarr {
+    fn app_name(&self) -> &'static str {
+        "Sonarr"
+    }
+
+    fn min_version(&self) -> u64 {
+        3
+    }
+
+    fn api_path(&self) -> &'static str {
+        "api/v3"
+    }
+
+    fn media_path(&self, media_id: i64) -> String {
+        format!("episode/{}", media_id)
+    }
+
+    fn file_path(&self, file_id: i64) -> String {
+        format!("episodefile/{}", file_id)
+    }
+
+    fn include_parent_param(&self) -> &'static str {
+        "includeSeries"
+    }
+
+    fn search_command(&self, media_ids: &[i64]) -> serde_json::Value {
+        json!({ "name": "EpisodeSearch", "episodeIds": media_ids })
+    }
+
+    fn season_search_command(&self, season: Season) -> Option<serde_json::Value> {
+        Some(json!({
+            "name": "SeasonSearch",
+            "seriesId": season.series_id,
+            "seasonNumber": season.season_number,
+        }))
+    }
+
+    fn season_path(&self, season: Season) -> Option<String> {
+        Some(format!(
+            "episode?seriesId={}&seasonNumber={}",
+            season.series_id, season.season_number
+        ))
+    }
+
+    fn parse_aired_episodes(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Vec<i64>>> {
+        let parsed = json::parse::<Vec<sonarr::Episode>>(body, diagnose)?;
+        let now = Utc::now();
+        Ok(parsed.map(|episodes| {
+            episodes
+                .iter()
+                .filter(|episode| {
+                    episode
+                        .air_date_utc
+                        .as_deref()
+                        .and_then(|aired| DateTime::parse_from_rfc3339(aired).ok())
+                        .is_some_and(|aired| aired <= now)
+                })
+                .filter_map(|episode| episode.id)
+                .collect()
+        }))
+    }
+
+    fn parse_queue(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn QueueJson>>> {
+        let parsed = json::parse::<sonarr::Queue>(body, diagnose)?;
+        Ok(parsed.map(|queue| Box::new(queue) as Box<dyn QueueJson>))
+    }
+
+    fn parse_media(
+        &self,
+        body: &[u8],
+        diagnose: bool,
+    ) -> serde_json::Result<Parsed<Box<dyn MediaItem>>> {
+        let parsed = json::parse::<sonarr::Episode>(body, diagnose)?;
+        Ok(parsed.map(|media| Box::new(media) as Box<dyn MediaItem>))
+    }
+
+    fn parse_grabs(&self, body: &[u8], diagnose: bool) -> serde_json::Result<Parsed<Vec<Grab>>> {
+        let parsed = json::parse::<Vec<sonarr::HistoryRecord>>(body, diagnose)?;
+        Ok(parsed.map(|history| {
+            history
+                .iter()
+                .filter_map(|grab| {
+                    Some(Grab {
+                        media_id: grab.episode_id?,
+                        title: grab.source_title.clone(),
+                        download_id: grab.download_id.clone().unwrap_or_default(),
+                    })
+                })
+                .collect()
+        }))
+    }
+}
+
+impl QueueJson for sonarr::Queue {
+    fn try_records(&self) -> Vec<Result<Record, String>> {
+        self.records.iter().map(Record::try_from).collect()
+    }
+
+    fn get_total_records(&self) -> i64 {
+        self.total_records
+    }
+
+    fn get_page_size(&self) -> i64 {
+        self.page_size
+    }
+}
+
+impl MediaItem for sonarr::Episode {
+    fn get_files(&self) -> Vec<MediaFile> {
+        let Some(id) = self.episode_file_id else {
+            return Vec::new();
+        };
+        let file = self.episode_file.as_ref();
+        vec![MediaFile {
+            id: Some(id),
+            scene_name: file
+                .and_then(|file| file.scene_name.as_deref())
+                .and_then(non_empty),
+            quality: file
+                .and_then(|file| file.quality.as_ref())
+                .map(|quality| quality.quality.name.clone()),
+            custom_format_score: file.and_then(|file| file.custom_format_score),
+        }]
+    }
+}
+
+impl TryFrom<&sonarr::Record> for Record {
+    type Error = String;
+
+    fn try_from(record: &sonarr::Record) -> Result<Self, String> {
+        queue_record!(
+            record,
+            media: episode_id,
+            parent: series_id => series.title,
+            season: record.series_id.zip(record.season_number).map(
+                |(series_id, season_number)| Season {
+                    series_id,
+                    season_number,
+                }
+            ),
+        )
+    }
+}
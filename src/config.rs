@@ -0,0 +1,238 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{de::Error, Deserialize, Deserializer};
+
+use crate::{
+    api::HttpConfig,
+    arr::Kind,
+    filter::FilterConfig,
+    reason::FailureReason,
+    rules::{PrivateTrackerConfig, RemovalConfig, Rule},
+    stall::StallConfig,
+};
+
+/// Config file listing every instance to check -- Ex:
+///
+/// ```toml
+/// [[instances]]
+/// name = "tv"
+/// kind = "sonarr"
+/// url = "http://localhost:8989"
+/// api_key_file = "/run/secrets/sonarr_api_key"
+/// skip_redownload = false
+/// ```
+#[derive(Default, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub instances: Vec<InstanceConfig>,
+    /// Rules applied to every instance, after the instance's own rules
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Stall thresholds for every instance without its own
+    #[serde(default)]
+    pub stall: StallConfig,
+    /// Records never acted on, for every instance without its own filter
+    #[serde(default)]
+    pub filter: FilterConfig,
+    /// Torrent indexers whose downloads keep seeding, for every instance without its own
+    #[serde(default)]
+    pub private_trackers: PrivateTrackerConfig,
+    /// Consecutive failed checks before a record is acted on, for every instance without its own
+    pub strikes: Option<u32>,
+    /// Time a record has to stay bad before it's acted on, for every instance without its own
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub strike_duration: Option<Duration>,
+    /// Directory where strikes, stall progress and the history of actions are saved
+    pub state_dir: Option<PathBuf>,
+    /// Number of queue records fetched per request
+    pub page_size: Option<i64>,
+    /// Process the queue one page at a time instead of fetching all of it first
+    #[serde(default)]
+    pub stream_pages: bool,
+    /// Report response fields that deviate from what's expected
+    #[serde(default)]
+    pub diagnose_json: bool,
+    /// Number of records acted on at the same time for each instance
+    pub concurrency: Option<usize>,
+    /// Timeouts and retries for every instance without its own
+    #[serde(default)]
+    pub http: HttpConfig,
+    /// Failure reasons `delete_file` rules may delete files for, for every instance without its own
+    #[serde(default)]
+    pub delete_file_reasons: Vec<FailureReason>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+
+        let config: Self = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse config file {}", path.display()))?;
+
+        // An empty page looks like the end of the queue, so a run would silently find nothing
+        ensure!(
+            config.page_size.is_none_or(|page_size| page_size >= 1),
+            "page_size in config file {} has to be at least 1",
+            path.display()
+        );
+
+        // Strikes and history are kept by instance name, so instances sharing one would overwrite each other
+        let mut names = HashSet::new();
+        for instance in &config.instances {
+            ensure!(
+                names.insert(instance.name.as_str()),
+                "Instance name {} is used more than once in config file {}",
+                instance.name,
+                path.display()
+            );
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Default, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstanceConfig {
+    pub name: String,
+    /// Detected from the instance when not set
+    pub kind: Option<Kind>,
+    pub url: String,
+    /// Only one of `api_key`, `api_key_file` and `api_key_env` can be set
+    pub api_key: Option<String>,
+    pub api_key_file: Option<PathBuf>,
+    pub api_key_env: Option<String>,
+    /// Same as `skip_redownload` in `removal`
+    #[serde(default)]
+    pub skip_redownload: bool,
+    #[serde(default)]
+    pub removal: RemovalConfig,
+    /// Search for a replacement after removing a record, instead of leaving it to the instance
+    #[serde(default)]
+    pub search: bool,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    pub delete_file_reasons: Option<Vec<FailureReason>>,
+    pub filter: Option<FilterConfig>,
+    pub private_trackers: Option<PrivateTrackerConfig>,
+    pub stall: Option<StallConfig>,
+    pub http: Option<HttpConfig>,
+    pub strikes: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub strike_duration: Option<Duration>,
+}
+
+impl InstanceConfig {
+    /// The API key, taken from the config itself, a file or an environment variable
+    pub fn resolve_api_key(&self) -> Result<String> {
+        match (&self.api_key, &self.api_key_file, &self.api_key_env) {
+            (Some(api_key), None, None) => Ok(api_key.clone()),
+            (None, Some(path), None) => read_api_key(path),
+            (None, None, Some(var)) => env::var(var).with_context(|| {
+                format!(
+                    "failed to read API key for {} from environment variable {}",
+                    self.name, var
+                )
+            }),
+            (None, None, None) => bail!(
+                "No API key for {}. Set api_key, api_key_file or api_key_env",
+                self.name
+            ),
+            _ => bail!(
+                "Only one of api_key, api_key_file or api_key_env can be set for {}",
+                self.name
+            ),
+        }
+    }
+}
+
+/// Read an API key from a file, ignoring surrounding whitespace
+pub fn read_api_key(path: &Path) -> Result<String> {
+    let api_key = fs::read_to_string(path)
+        .with_context(|| format!("failed to read API key file {}", path.display()))?
+        .trim()
+        .to_string();
+    ensure!(
+        !api_key.is_empty(),
+        "API key file {} is empty",
+        path.display()
+    );
+    Ok(api_key)
+}
+
+/// Parse an optional human readable duration -- Ex: 30m, 2h 30m
+pub fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|duration| humantime::parse_duration(&duration).map_err(D::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance() -> InstanceConfig {
+        InstanceConfig {
+            name: "tv".to_string(),
+            url: "http://localhost:8989".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Write an API key file unique to the test, so tests running together don't share one
+    fn key_file(test: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "auto_blocklist_{}_{}.key",
+            test,
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn resolves_the_one_key_source_set() {
+        let mut from_config = instance();
+        from_config.api_key = Some("from config".to_string());
+        assert_eq!(from_config.resolve_api_key().unwrap(), "from config");
+
+        let mut from_file = instance();
+        from_file.api_key_file = Some(key_file("resolves", "  from file\n"));
+        assert_eq!(from_file.resolve_api_key().unwrap(), "from file");
+
+        let mut from_env = instance();
+        env::set_var("AUTO_BLOCKLIST_TEST_API_KEY", "from env");
+        from_env.api_key_env = Some("AUTO_BLOCKLIST_TEST_API_KEY".to_string());
+        assert_eq!(from_env.resolve_api_key().unwrap(), "from env");
+    }
+
+    #[test]
+    fn needs_exactly_one_key_source() {
+        assert!(instance().resolve_api_key().is_err());
+
+        let mut both = instance();
+        both.api_key = Some("from config".to_string());
+        both.api_key_file = Some(key_file("needs_one", "from file"));
+        assert!(both.resolve_api_key().is_err());
+
+        let mut unset_env = instance();
+        unset_env.api_key_env = Some("AUTO_BLOCKLIST_TEST_UNSET_API_KEY".to_string());
+        assert!(unset_env.resolve_api_key().is_err());
+    }
+
+    #[test]
+    fn rejects_an_empty_key_file() {
+        let mut empty = instance();
+        empty.api_key_file = Some(key_file("empty", " \n"));
+        assert!(empty.resolve_api_key().is_err());
+    }
+}
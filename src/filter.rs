@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::api::Record;
+
+/// Records to leave alone, whatever the rules say. A record has to match `include` when anything is set
+/// in it, and must not match `exclude` -- Ex:
+///
+/// ```toml
+/// [filter.exclude]
+/// titles = ["Hand Managed Show"]
+/// tags = ["manual"]
+/// indexers = ["PrivateHD"]
+/// ```
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterConfig {
+    #[serde(default)]
+    pub include: Selector,
+    #[serde(default)]
+    pub exclude: Selector,
+}
+
+/// Matches records on any of the listed values. Strings are compared case-insensitively.
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Selector {
+    /// Series, movie, artist or author ids
+    pub ids: Vec<i64>,
+    /// Series, movie, artist or author titles
+    pub titles: Vec<String>,
+    /// Tag labels of the series, movie, artist or author
+    pub tags: Vec<String>,
+    pub indexers: Vec<String>,
+    pub download_clients: Vec<String>,
+    /// Quality profile ids of the series, movie, artist or author
+    pub quality_profiles: Vec<i64>,
+}
+
+impl Selector {
+    fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+            && self.titles.is_empty()
+            && self.tags.is_empty()
+            && self.indexers.is_empty()
+            && self.download_clients.is_empty()
+            && self.quality_profiles.is_empty()
+    }
+
+    /// Whether the records' series, movie, artist or author is needed to match
+    fn needs_parent(&self) -> bool {
+        !self.titles.is_empty() || !self.tags.is_empty() || !self.quality_profiles.is_empty()
+    }
+
+    /// What the record matched on, if anything
+    fn matches(&self, record: &Record, tags: &HashMap<i64, String>) -> Option<String> {
+        fn contains(values: &[String], value: &str) -> bool {
+            values.iter().any(|v| v.eq_ignore_ascii_case(value))
+        }
+
+        let parent = &record.parent;
+        if let Some(id) = parent.id.filter(|id| self.ids.contains(id)) {
+            return Some(format!("id {}", id));
+        }
+        if let Some(title) = parent
+            .title
+            .as_deref()
+            .filter(|t| contains(&self.titles, t))
+        {
+            return Some(format!("title {}", title));
+        }
+        if let Some(label) = parent
+            .tags
+            .iter()
+            .filter_map(|tag| tags.get(tag))
+            .find(|label| contains(&self.tags, label))
+        {
+            return Some(format!("tag {}", label));
+        }
+        if contains(&self.indexers, &record.indexer) {
+            return Some(format!("indexer {}", record.indexer));
+        }
+        if contains(&self.download_clients, &record.download_client) {
+            return Some(format!("download client {}", record.download_client));
+        }
+        if let Some(profile) = parent
+            .quality_profile_id
+            .filter(|profile| self.quality_profiles.contains(profile))
+        {
+            return Some(format!("quality profile {}", profile));
+        }
+        None
+    }
+}
+
+impl FilterConfig {
+    /// Whether tag labels have to be looked up to match
+    pub fn uses_tags(&self) -> bool {
+        !self.include.tags.is_empty() || !self.exclude.tags.is_empty()
+    }
+
+    /// Whether the queue has to be fetched with each record's series, movie, artist or author
+    pub fn needs_parent(&self) -> bool {
+        self.include.needs_parent() || self.exclude.needs_parent()
+    }
+
+    /// Why the record is left alone, or None if it may be acted on.
+    /// `tags` are the instance's tag labels by id.
+    pub fn skip_reason(&self, record: &Record, tags: &HashMap<i64, String>) -> Option<String> {
+        if let Some(matched) = self.exclude.matches(record, tags) {
+            return Some(format!("excluded by {}", matched));
+        }
+        if !self.include.is_empty() && self.include.matches(record, tags).is_none() {
+            return Some("not included by the filter".to_string());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(toml: &str) -> FilterConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn record() -> Record {
+        let mut record = Record::example(1, "a");
+        record.indexer = "PrivateHD".to_string();
+        record.download_client = "qBittorrent".to_string();
+        record.parent.id = Some(7);
+        record.parent.title = Some("Hand Managed Show".to_string());
+        record.parent.tags = vec![1, 2];
+        record.parent.quality_profile_id = Some(5);
+        record
+    }
+
+    fn tags() -> HashMap<i64, String> {
+        HashMap::from([(1, "anime".to_string()), (2, "Manual".to_string())])
+    }
+
+    #[test]
+    fn empty_filter_skips_nothing() {
+        assert_eq!(filter("").skip_reason(&record(), &tags()), None);
+    }
+
+    #[test]
+    fn excludes_by_what_matched() {
+        let skip = |toml| filter(toml).skip_reason(&record(), &tags());
+        assert_eq!(
+            skip("[exclude]\nids = [7]"),
+            Some("excluded by id 7".to_string())
+        );
+        assert_eq!(
+            skip("[exclude]\ntitles = [\"hand managed show\"]"),
+            Some("excluded by title Hand Managed Show".to_string())
+        );
+        assert_eq!(
+            skip("[exclude]\ntags = [\"manual\"]"),
+            Some("excluded by tag Manual".to_string())
+        );
+        assert_eq!(
+            skip("[exclude]\nindexers = [\"privatehd\"]"),
+            Some("excluded by indexer PrivateHD".to_string())
+        );
+        assert_eq!(
+            skip("[exclude]\nquality_profiles = [5]"),
+            Some("excluded by quality profile 5".to_string())
+        );
+        assert_eq!(skip("[exclude]\ndownload_clients = [\"SABnzbd\"]"), None);
+    }
+
+    #[test]
+    fn include_skips_everything_else() {
+        let included = filter("[include]\nquality_profiles = [5, 6]");
+        assert_eq!(included.skip_reason(&record(), &tags()), None);
+
+        let other = filter("[include]\ntags = [\"4k\"]");
+        assert_eq!(
+            other.skip_reason(&record(), &tags()),
+            Some("not included by the filter".to_string())
+        );
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = filter("[include]\nids = [7]\n[exclude]\ntags = [\"anime\"]");
+        assert_eq!(
+            filter.skip_reason(&record(), &tags()),
+            Some("excluded by tag anime".to_string())
+        );
+    }
+
+    #[test]
+    fn knows_what_it_needs_fetched() {
+        let by_indexer = filter("[exclude]\nindexers = [\"PrivateHD\"]");
+        assert!(!by_indexer.uses_tags() && !by_indexer.needs_parent());
+        let by_tag = filter("[include]\ntags = [\"manual\"]");
+        assert!(by_tag.uses_tags() && by_tag.needs_parent());
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{reason::FailureReason, rules::Action};
+
+/// One action taken on a queue record, and which API calls succeeded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    pub instance: String,
+    pub download_id: String,
+    pub record_id: i64,
+    pub title: String,
+    pub indexer: String,
+    pub reason: FailureReason,
+    pub rule: String,
+    pub action: Action,
+    /// None when the action didn't need the file deleted, or it was kept
+    pub file_deleted: Option<bool>,
+    /// Why the file wasn't deleted although the action asked for it: there was none, or it's worth keeping
+    #[serde(default)]
+    pub file_kept: Option<String>,
+    pub removed: bool,
+    pub blocklisted: bool,
+    /// Whether a search after removing it grabbed a new release, None when there was no search
+    #[serde(default)]
+    pub grabbed: Option<bool>,
+}
+
+/// Audit trail of every action taken, stored as one JSON object per line
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn append(&self, entries: &[Entry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .with_context(|| format!("failed to write history file {}", self.path.display()))
+    }
+
+    pub fn load(&self) -> Result<Vec<Entry>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("failed to read history file {}", self.path.display())
+                })
+            }
+        };
+
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!(
+                        "failed to parse line {} of history file {}",
+                        number + 1,
+                        self.path.display()
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// A history file unique to the test, so tests running together don't share one
+    fn history(test: &str) -> History {
+        let path = env::temp_dir().join(format!(
+            "auto_blocklist_{}_{}.jsonl",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        History::new(path)
+    }
+
+    fn entry(record_id: i64) -> Entry {
+        Entry {
+            timestamp: Utc::now(),
+            instance: "tv".to_string(),
+            download_id: format!("download{}", record_id),
+            record_id,
+            title: format!("Release {}", record_id),
+            indexer: "Indexer".to_string(),
+            reason: FailureReason::Sample,
+            rule: "sample".to_string(),
+            action: Action::DeleteFile,
+            file_deleted: None,
+            file_kept: Some("no file".to_string()),
+            removed: true,
+            blocklisted: true,
+            grabbed: Some(false),
+        }
+    }
+
+    #[test]
+    fn loads_what_was_appended() {
+        let history = history("round_trip");
+        assert!(history.load().unwrap().is_empty());
+        history.append(&[entry(1), entry(2)]).unwrap();
+        history.append(&[entry(3)]).unwrap();
+
+        let entries = history.load().unwrap();
+        fs::remove_file(&history.path).unwrap();
+        let ids: Vec<i64> = entries.iter().map(|entry| entry.record_id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(entries[0].download_id, "download1");
+        assert_eq!(entries[0].reason, FailureReason::Sample);
+        assert_eq!(entries[0].action, Action::DeleteFile);
+        assert_eq!(entries[0].file_kept.as_deref(), Some("no file"));
+        assert_eq!(entries[0].grabbed, Some(false));
+    }
+
+    #[test]
+    fn loads_lines_written_before_newer_fields() {
+        let history = history("old_line");
+        // Written before `file_kept` and `grabbed` were added
+        fs::write(
+            &history.path,
+            r#"{"timestamp":"2024-05-01T12:00:00Z","instance":"tv","download_id":"abc","record_id":7,"title":"Release","indexer":"Indexer","reason":"sample","rule":"sample","action":"delete_file","file_deleted":true,"removed":true,"blocklisted":true}
+"#,
+        )
+        .unwrap();
+
+        let entries = history.load().unwrap();
+        fs::remove_file(&history.path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].record_id, 7);
+        assert_eq!(entries[0].file_deleted, Some(true));
+        assert_eq!(entries[0].file_kept, None);
+        assert_eq!(entries[0].grabbed, None);
+    }
+
+    #[test]
+    fn reports_the_line_that_failed_to_parse() {
+        let history = history("malformed");
+        history.append(&[entry(1)]).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&history.path)
+            .and_then(|mut file| file.write_all(b"\n{\"record_id\":\n"))
+            .unwrap();
+
+        let error = history.load().unwrap_err();
+        fs::remove_file(&history.path).unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "failed to parse line 3 of history file {}",
+                history.path.display()
+            )
+        );
+    }
+}
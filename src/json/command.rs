@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+use super::{nullable, Extra};
+use crate::status::CommandStatus;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Command {
+    #[serde(deserialize_with = "nullable")]
+    pub id: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub status: CommandStatus,
+    pub message: Option<String>,
+    /// When the instance queued the command, in its own clock
+    pub queued: Option<String>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+
+use super::{nullable, Extra};
+use crate::status::{DownloadProtocol, QueueStatus, TrackedDownloadState, TrackedDownloadStatus};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Queue {
+    #[serde(deserialize_with = "nullable")]
+    pub page: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub page_size: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub total_records: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub records: Vec<Record>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Record {
+    pub id: Option<i64>,
+    pub artist_id: Option<i64>,
+    pub album_id: Option<i64>,
+    pub artist: Option<Artist>,
+    pub quality: Option<Quality>,
+    pub custom_format_score: Option<i64>,
+    #[serde(deserialize_with = "nullable")]
+    pub size: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub title: String,
+    #[serde(deserialize_with = "nullable")]
+    pub sizeleft: i64,
+    pub timeleft: Option<String>,
+    pub estimated_completion_time: Option<String>,
+    pub added: Option<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub status: QueueStatus,
+    #[serde(deserialize_with = "nullable")]
+    pub tracked_download_status: TrackedDownloadStatus,
+    #[serde(deserialize_with = "nullable")]
+    pub tracked_download_state: TrackedDownloadState,
+    #[serde(deserialize_with = "nullable")]
+    pub status_messages: Vec<StatusMessage>,
+    pub error_message: Option<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub download_id: String,
+    #[serde(deserialize_with = "nullable")]
+    pub protocol: DownloadProtocol,
+    #[serde(deserialize_with = "nullable")]
+    pub download_client: String,
+    pub download_client_has_post_import_category: Option<bool>,
+    #[serde(deserialize_with = "nullable")]
+    pub indexer: String,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Artist {
+    #[serde(deserialize_with = "nullable")]
+    pub id: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub artist_name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub quality_profile_id: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub tags: Vec<i64>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TrackFile {
+    pub id: Option<i64>,
+    #[serde(deserialize_with = "nullable")]
+    pub album_id: i64,
+    pub scene_name: Option<String>,
+    pub quality: Option<Quality>,
+    pub custom_format_score: Option<i64>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Quality {
+    #[serde(deserialize_with = "nullable")]
+    pub quality: QualityDetail,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct QualityDetail {
+    #[serde(deserialize_with = "nullable")]
+    pub id: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub name: String,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
+
+/// An event in the history, only read for grabs
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HistoryRecord {
+    #[serde(deserialize_with = "nullable")]
+    pub id: i64,
+    pub album_id: Option<i64>,
+    #[serde(deserialize_with = "nullable")]
+    pub source_title: String,
+    pub download_id: Option<String>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StatusMessage {
+    #[serde(deserialize_with = "nullable")]
+    pub title: String,
+    #[serde(deserialize_with = "nullable")]
+    pub messages: Vec<String>,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
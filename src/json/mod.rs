@@ -1,2 +1,444 @@
+pub mod command;
+pub mod lidarr;
 pub mod radarr;
+pub mod readarr;
 pub mod sonarr;
+pub mod system;
+pub mod tag;
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// Fields sent by the API that the models don't use
+pub type Extra = HashMap<String, Value>;
+
+/// Treat null the same as a missing field and use the default value.
+/// Not for ids, which end up in requests that change things, so a missing one has to stay missing.
+pub fn nullable<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// A parsed response, and where it deviated from the model
+pub struct Parsed<T> {
+    pub value: T,
+    pub deviations: Vec<String>,
+}
+
+impl<T> Parsed<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Parsed<U> {
+        Parsed {
+            value: f(self.value),
+            deviations: self.deviations,
+        }
+    }
+}
+
+/// Parse a response body.
+/// With `diagnose` set, also list the fields that were unknown, or missing or null and so defaulted.
+pub fn parse<T: DeserializeOwned + Serialize>(
+    body: &[u8],
+    diagnose: bool,
+) -> serde_json::Result<Parsed<T>> {
+    if !diagnose {
+        return Ok(Parsed {
+            value: serde_json::from_slice(body)?,
+            deviations: Vec::new(),
+        });
+    }
+
+    let raw: Value = serde_json::from_slice(body)?;
+    let value = T::deserialize(&raw)?;
+    let mut deviations = BTreeSet::new();
+    compare("", &raw, &serde_json::to_value(&value)?, &mut deviations);
+
+    Ok(Parsed {
+        value,
+        deviations: deviations.into_iter().collect(),
+    })
+}
+
+/// Compare the response with the model it was parsed into.
+/// Optional fields are expected to be missing sometimes, so they're only reported when unknown.
+fn compare(path: &str, raw: &Value, model: &Value, deviations: &mut BTreeSet<String>) {
+    match (raw, model) {
+        (Value::Object(raw), Value::Object(model)) => {
+            for (key, model_value) in model {
+                let path = format!("{}{}", path, key);
+                match raw.get(key) {
+                    None if !model_value.is_null() => {
+                        deviations.insert(format!("missing field {}, defaulted", path));
+                    }
+                    Some(Value::Null) if !model_value.is_null() => {
+                        deviations.insert(format!("null field {}, defaulted", path));
+                    }
+                    Some(raw_value) => {
+                        compare(&format!("{}.", path), raw_value, model_value, deviations)
+                    }
+                    None => {}
+                }
+            }
+            for key in raw.keys().filter(|key| !model.contains_key(*key)) {
+                deviations.insert(format!("unknown field {}{}", path, key));
+            }
+        }
+        (Value::Array(raw), Value::Array(model)) => {
+            let path = format!("{}[].", path.trim_end_matches('.'));
+            for (raw, model) in raw.iter().zip(model) {
+                compare(&path, raw, model, deviations);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arr::{MediaItem, QueueJson},
+        reason::FailureReason,
+        status::{DownloadProtocol, QueueStatus, TrackedDownloadState},
+    };
+
+    /// A Sonarr v4 queue page, trimmed to one record, with `indexer` null and `sizeleft` left out
+    const SONARR_QUEUE: &str = r#"{
+        "page": 1,
+        "pageSize": 10,
+        "sortKey": "timeleft",
+        "sortDirection": "ascending",
+        "totalRecords": 1,
+        "records": [{
+            "seriesId": 1,
+            "episodeId": 101,
+            "seasonNumber": 1,
+            "languages": [{"id": 1, "name": "English"}],
+            "quality": {
+                "quality": {"id": 3, "name": "WEBDL-1080p", "source": "web", "resolution": 1080},
+                "revision": {"version": 1, "real": 0, "isRepack": false}
+            },
+            "customFormats": [],
+            "customFormatScore": 0,
+            "size": 1572864000,
+            "title": "Show.S01E01.1080p.WEB-DL.x264-GRP",
+            "timeleft": "00:00:00",
+            "estimatedCompletionTime": "2024-05-01T10:00:00Z",
+            "added": "2024-05-01T09:00:00Z",
+            "status": "completed",
+            "trackedDownloadStatus": "warning",
+            "trackedDownloadState": "importPending",
+            "statusMessages": [{
+                "title": "Show.S01E01.1080p.WEB-DL.x264-GRP.mkv",
+                "messages": ["Not an upgrade for existing episode file(s). Existing quality: WEBDL-1080p. New Quality WEBDL-1080p."]
+            }],
+            "errorMessage": null,
+            "downloadId": "ABCDEF0123456789",
+            "protocol": "torrent",
+            "downloadClient": "qBittorrent",
+            "downloadClientHasPostImportCategory": false,
+            "indexer": null,
+            "outputPath": "/downloads/Show.S01E01.1080p.WEB-DL.x264-GRP",
+            "episodeHasFile": true,
+            "id": 42
+        }]
+    }"#;
+
+    #[test]
+    fn parses_a_sonarr_queue_page() {
+        let parsed = parse::<sonarr::Queue>(SONARR_QUEUE.as_bytes(), true).unwrap();
+        assert_eq!(parsed.value.records[0].extra["episodeHasFile"], true);
+
+        let records = parsed.value.get_records();
+        let record = &records[0];
+        assert_eq!(record.id, 42);
+        assert_eq!(record.media_id, 101);
+        assert_eq!(record.parent.id, Some(1));
+        assert_eq!(record.download_id, "ABCDEF0123456789");
+        assert_eq!(record.status, QueueStatus::Completed);
+        assert_eq!(record.tracked_state, TrackedDownloadState::ImportPending);
+        assert_eq!(record.protocol, DownloadProtocol::Torrent);
+        assert_eq!(record.quality.as_deref(), Some("WEBDL-1080p"));
+        assert_eq!(record.custom_format_score, Some(0));
+        assert_eq!(record.reason, FailureReason::NotAnUpgrade);
+        assert_eq!(record.indexer, "");
+        assert_eq!(record.sizeleft, 0);
+        assert_eq!(record.error_message, None);
+
+        assert_eq!(
+            parsed.deviations,
+            [
+                "missing field records[].sizeleft, defaulted",
+                "null field records[].indexer, defaulted",
+                "unknown field records[].customFormats",
+                "unknown field records[].episodeHasFile",
+                "unknown field records[].languages",
+                "unknown field records[].outputPath",
+                "unknown field records[].quality.quality.resolution",
+                "unknown field records[].quality.quality.source",
+                "unknown field records[].quality.revision",
+                "unknown field sortDirection",
+                "unknown field sortKey",
+            ]
+        );
+    }
+
+    const LIDARR_QUEUE: &str = r#"{
+        "page": 1,
+        "pageSize": 10,
+        "sortKey": "timeleft",
+        "sortDirection": "ascending",
+        "totalRecords": 1,
+        "records": [{
+            "artistId": 5,
+            "albumId": 17,
+            "artist": {
+                "artistMetadataId": 5,
+                "status": "continuing",
+                "ended": false,
+                "artistName": "The Band",
+                "foreignArtistId": "0383dadf-2a4e-4d10-a46a-e9e041da8eb3",
+                "qualityProfileId": 2,
+                "metadataProfileId": 1,
+                "monitored": true,
+                "tags": [3],
+                "id": 5
+            },
+            "quality": {
+                "quality": {"id": 6, "name": "FLAC"},
+                "revision": {"version": 1, "real": 0, "isRepack": false}
+            },
+            "customFormats": [],
+            "customFormatScore": 0,
+            "size": 314572800,
+            "title": "The Band - Album (2020) [FLAC]",
+            "sizeleft": 0,
+            "timeleft": "00:00:00",
+            "estimatedCompletionTime": "2024-05-01T10:00:00Z",
+            "added": "2024-05-01T09:00:00Z",
+            "status": "completed",
+            "trackedDownloadStatus": "warning",
+            "trackedDownloadState": "importPending",
+            "statusMessages": [{
+                "title": "01 - Song.flac",
+                "messages": ["Not an upgrade for existing track file(s)"]
+            }],
+            "downloadId": "0123456789ABCDEF",
+            "protocol": "torrent",
+            "downloadClient": "qBittorrent",
+            "downloadClientHasPostImportCategory": true,
+            "indexer": "Indexer",
+            "outputPath": "/downloads/The Band - Album (2020) [FLAC]",
+            "trackFileCount": 10,
+            "trackHasFileCount": 10,
+            "downloadForced": false,
+            "id": 77
+        }]
+    }"#;
+
+    const LIDARR_TRACK_FILES: &str = r#"[
+        {
+            "artistId": 5,
+            "albumId": 17,
+            "path": "/music/The Band/Album (2020)/01 - Song.flac",
+            "size": 31457280,
+            "dateAdded": "2023-01-01T00:00:00Z",
+            "sceneName": "The Band - Album (2020) [FLAC]",
+            "releaseGroup": "GRP",
+            "quality": {
+                "quality": {"id": 6, "name": "FLAC"},
+                "revision": {"version": 1, "real": 0, "isRepack": false}
+            },
+            "qualityWeight": 1401,
+            "qualityCutoffNotMet": false,
+            "id": 301
+        },
+        {
+            "artistId": 5,
+            "albumId": 17,
+            "path": "/music/The Band/Album (2020)/02 - Other Song.flac",
+            "size": 29360128,
+            "dateAdded": "2023-01-01T00:00:00Z",
+            "sceneName": "",
+            "quality": {
+                "quality": {"id": 6, "name": "FLAC"},
+                "revision": {"version": 1, "real": 0, "isRepack": false}
+            },
+            "qualityWeight": 1401,
+            "qualityCutoffNotMet": false,
+            "id": 302
+        }
+    ]"#;
+
+    #[test]
+    fn parses_a_lidarr_queue_page() {
+        let parsed = parse::<lidarr::Queue>(LIDARR_QUEUE.as_bytes(), false).unwrap();
+        let records = parsed.value.get_records();
+        let record = &records[0];
+        assert_eq!(record.id, 77);
+        assert_eq!(record.media_id, 17);
+        assert_eq!(record.parent.id, Some(5));
+        assert_eq!(record.parent.title.as_deref(), Some("The Band"));
+        assert_eq!(record.parent.tags, [3]);
+        assert_eq!(record.parent.quality_profile_id, Some(2));
+        assert_eq!(record.season, None);
+        assert_eq!(record.download_id, "0123456789ABCDEF");
+        assert_eq!(record.quality.as_deref(), Some("FLAC"));
+        assert_eq!(record.reason, FailureReason::NotAnUpgrade);
+    }
+
+    #[test]
+    fn parses_lidarr_track_files() {
+        let parsed = parse::<Vec<lidarr::TrackFile>>(LIDARR_TRACK_FILES.as_bytes(), false).unwrap();
+        let files = parsed.value.get_files();
+        let ids: Vec<Option<i64>> = files.iter().map(|file| file.id).collect();
+        assert_eq!(ids, [Some(301), Some(302)]);
+        assert_eq!(
+            files[0].scene_name.as_deref(),
+            Some("The Band - Album (2020) [FLAC]")
+        );
+        assert_eq!(files[0].quality.as_deref(), Some("FLAC"));
+        // An empty scene name is the same as none
+        assert_eq!(files[1].scene_name, None);
+    }
+
+    const READARR_QUEUE: &str = r#"{
+        "page": 1,
+        "pageSize": 10,
+        "sortKey": "timeleft",
+        "sortDirection": "ascending",
+        "totalRecords": 1,
+        "records": [{
+            "authorId": 8,
+            "bookId": 23,
+            "author": {
+                "authorMetadataId": 8,
+                "status": "continuing",
+                "ended": false,
+                "authorName": "Jane Writer",
+                "foreignAuthorId": "12345",
+                "qualityProfileId": 1,
+                "metadataProfileId": 1,
+                "monitored": true,
+                "tags": [],
+                "id": 8
+            },
+            "quality": {
+                "quality": {"id": 3, "name": "EPUB"},
+                "revision": {"version": 1, "real": 0, "isRepack": false}
+            },
+            "size": 1048576,
+            "title": "Jane Writer - The Book (2021) [EPUB]",
+            "sizeleft": 524288,
+            "timeleft": "00:05:00",
+            "estimatedCompletionTime": "2024-05-01T10:05:00Z",
+            "added": "2024-05-01T10:00:00Z",
+            "status": "downloading",
+            "trackedDownloadStatus": "ok",
+            "trackedDownloadState": "downloading",
+            "statusMessages": [],
+            "downloadId": "SABnzbd_nzo_abc123",
+            "protocol": "usenet",
+            "downloadClient": "SABnzbd",
+            "downloadClientHasPostImportCategory": false,
+            "indexer": "Indexer",
+            "outputPath": "/downloads/Jane Writer - The Book (2021) [EPUB]",
+            "downloadForced": false,
+            "id": 88
+        }]
+    }"#;
+
+    const READARR_BOOK_FILES: &str = r#"[
+        {
+            "authorId": 8,
+            "bookId": 23,
+            "path": "/books/Jane Writer/The Book (2021)/The Book.epub",
+            "size": 1048576,
+            "dateAdded": "2023-01-01T00:00:00Z",
+            "sceneName": "Jane Writer - The Book (2021) [EPUB]",
+            "releaseGroup": "GRP",
+            "quality": {
+                "quality": {"id": 3, "name": "EPUB"},
+                "revision": {"version": 1, "real": 0, "isRepack": false}
+            },
+            "qualityWeight": 301,
+            "qualityCutoffNotMet": false,
+            "audioTags": null,
+            "id": 401
+        }
+    ]"#;
+
+    #[test]
+    fn parses_a_readarr_queue_page() {
+        let parsed = parse::<readarr::Queue>(READARR_QUEUE.as_bytes(), false).unwrap();
+        let records = parsed.value.get_records();
+        let record = &records[0];
+        assert_eq!(record.id, 88);
+        assert_eq!(record.media_id, 23);
+        assert_eq!(record.parent.id, Some(8));
+        assert_eq!(record.parent.title.as_deref(), Some("Jane Writer"));
+        assert_eq!(record.parent.quality_profile_id, Some(1));
+        assert_eq!(record.season, None);
+        assert_eq!(record.download_id, "SABnzbd_nzo_abc123");
+        assert_eq!(record.status, QueueStatus::Downloading);
+        assert_eq!(record.protocol, DownloadProtocol::Usenet);
+        assert_eq!(record.sizeleft, 524288);
+        assert_eq!(record.quality.as_deref(), Some("EPUB"));
+    }
+
+    #[test]
+    fn parses_readarr_book_files() {
+        let parsed = parse::<Vec<readarr::BookFile>>(READARR_BOOK_FILES.as_bytes(), false).unwrap();
+        let files = parsed.value.get_files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].id, Some(401));
+        assert_eq!(
+            files[0].scene_name.as_deref(),
+            Some("Jane Writer - The Book (2021) [EPUB]")
+        );
+        assert_eq!(files[0].quality.as_deref(), Some("EPUB"));
+        assert_eq!(files[0].custom_format_score, None);
+    }
+
+    #[test]
+    fn skips_records_missing_an_id() {
+        let body = r#"{
+            "page": 1,
+            "pageSize": 10,
+            "totalRecords": 3,
+            "records": [
+                {"episodeId": 101, "seriesId": 1, "seasonNumber": 1, "title": "No.Id"},
+                {"id": 42, "episodeId": null, "seriesId": 1, "seasonNumber": 1, "title": "No.Episode"},
+                {"id": 43, "episodeId": 103, "title": "No.Series"}
+            ]
+        }"#;
+        let queue = parse::<sonarr::Queue>(body.as_bytes(), false)
+            .unwrap()
+            .value;
+
+        let errors: Vec<String> = queue
+            .try_records()
+            .into_iter()
+            .filter_map(Result::err)
+            .collect();
+        assert_eq!(errors, ["No.Id has no id", "No.Episode has no episode_id"]);
+
+        // Without its series the record is still usable, it just can't be searched for as a season
+        let records = queue.get_records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, 43);
+        assert_eq!(records[0].season, None);
+        assert_eq!(records[0].parent.id, None);
+    }
+
+    #[test]
+    fn skips_the_comparison_unless_diagnosing() {
+        let parsed = parse::<sonarr::Queue>(SONARR_QUEUE.as_bytes(), false).unwrap();
+        assert_eq!(parsed.value.total_records, 1);
+        assert!(parsed.deviations.is_empty());
+    }
+}
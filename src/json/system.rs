@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+use super::{nullable, Extra};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Status {
+    #[serde(deserialize_with = "nullable")]
+    pub app_name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub version: String,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
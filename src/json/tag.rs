@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+use super::{nullable, Extra};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Tag {
+    #[serde(deserialize_with = "nullable")]
+    pub id: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub label: String,
+    #[serde(flatten, skip_serializing)]
+    pub extra: Extra,
+}
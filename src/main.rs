@@ -1,60 +1,1610 @@
 mod api;
+mod arr;
+mod config;
+mod filter;
+mod history;
 mod json;
+mod reason;
+mod rules;
+mod stall;
+mod status;
+mod strikes;
 
-use anyhow::Result;
-use api::{Api, Record};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use api::{Api, Files, Grab, Record, Refused, RemovalFlags, Season};
+use arr::Kind;
+use chrono::{SecondsFormat, Utc};
 use clap::Parser;
+use config::{Config, InstanceConfig};
+use filter::FilterConfig;
+use futures::{future, stream, StreamExt};
+use history::{Entry, History};
+use json::command::Command;
+use reason::FailureReason;
+use rules::{Action, Decision, RuleSet};
+use stall::{StallConfig, StallTracker};
+use status::CommandStatus;
+use strikes::{StrikePolicy, StrikeStore};
+use tokio::sync::watch;
 
-/// Automatically delete and request failed downloads and imports from Sonarr/Radarr
+/// Automatically delete and request failed downloads and imports from Sonarr/Radarr/Lidarr/Readarr
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Url for Sonarr/Radarr instance -- Ex: http://localhost:8989
-    #[arg(value_parser)]
-    url: String,
+    /// Url for Sonarr/Radarr/Lidarr/Readarr instance -- Ex: http://localhost:8989
+    #[arg(value_parser, required_unless_present_any = ["config", "history"])]
+    url: Option<String>,
 
-    /// Sonarr/Radarr API key
-    #[arg(value_parser)]
-    api_key: String,
+    /// Sonarr/Radarr API key. Prefer --api-key-file or ARR_API_KEY, which don't show up in `ps`
+    #[arg(value_parser, env = "ARR_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
 
-    /// Use Radarr parsing instead of Sonarr
-    #[arg(long, value_parser, default_value = "false")]
+    /// Read the Sonarr/Radarr API key from this file
+    #[arg(long, value_parser, requires = "url")]
+    api_key_file: Option<PathBuf>,
+
+    /// Type of instance, detected from the instance when not given
+    #[arg(long, value_enum, requires = "url")]
+    kind: Option<Kind>,
+
+    /// Use Radarr parsing instead of Sonarr, same as --kind radarr
+    #[arg(
+        long,
+        value_parser,
+        default_value = "false",
+        conflicts_with = "kind",
+        requires = "url"
+    )]
     radarr: bool,
 
     /// Skip attempting to redownload release
-    #[arg(long, value_parser, default_value = "false")]
+    #[arg(long, value_parser, default_value = "false", requires = "url")]
     skip_redownload: bool,
+
+    /// Search for a replacement after removing a record and report whether one was grabbed
+    #[arg(long, value_parser, default_value = "false", requires = "url")]
+    search: bool,
+
+    /// TOML file listing any number of Sonarr/Radarr instances to check
+    #[arg(long, value_parser)]
+    config: Option<PathBuf>,
+
+    /// Print the records, files and flags that would be used without deleting anything
+    #[arg(long, value_parser, default_value = "false")]
+    dry_run: bool,
+
+    /// Keep running and check the queue again after this long -- Ex: 15m, 1h
+    #[arg(long, value_parser = humantime::parse_duration)]
+    interval: Option<Duration>,
+
+    /// Directory where strikes, stall progress and the history of actions are saved [default: current directory]
+    #[arg(long, value_parser)]
+    state_dir: Option<PathBuf>,
+
+    /// Print every action taken so far and exit
+    #[arg(long, value_parser, default_value = "false")]
+    history: bool,
+
+    /// Number of queue records fetched per request [default: 1000]
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    page_size: Option<i64>,
+
+    /// Process the queue one page at a time instead of fetching all of it first, to bound memory use
+    #[arg(long, value_parser, default_value = "false")]
+    stream_pages: bool,
+
+    /// Report response fields that were unknown, or missing or null and so defaulted
+    #[arg(long, value_parser, default_value = "false")]
+    diagnose_json: bool,
+
+    /// Number of records acted on at the same time for each instance [default: 4]
+    #[arg(long, value_parser)]
+    concurrency: Option<usize>,
+
+    /// Failure reasons the file of a failed record may be deleted for, none unless given -- Ex: sample,dangerous_file
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "REASONS",
+        hide_possible_values = true
+    )]
+    delete_file_reasons: Vec<FailureReason>,
+}
+
+/// A named Sonarr/Radarr instance to check
+struct Instance {
+    name: String,
+    api: Api,
+    rules: RuleSet,
+    filter: FilterConfig,
+    stall: StallConfig,
+    strikes: StrikePolicy,
+    stream_pages: bool,
+    /// Records acted on at the same time
+    concurrency: usize,
+    /// Search for replacements after removing records
+    search: bool,
+}
+
+impl Instance {
+    const DEFAULT_CONCURRENCY: usize = 4;
+
+    /// Build an instance from its config, falling back to the global config for anything it doesn't set.
+    /// The instance's own rules are checked before the global ones.
+    fn new(instance: InstanceConfig, config: &Config) -> Result<Self> {
+        let api_key = instance.resolve_api_key()?;
+        let mut rules = instance.rules;
+        rules.extend_from_slice(&config.rules);
+        let filter = instance.filter.unwrap_or_else(|| config.filter.clone());
+        let mut removal = instance.removal;
+        if instance.skip_redownload {
+            removal.skip_redownload.get_or_insert(true);
+        }
+
+        Ok(Self {
+            name: instance.name,
+            api: Api::new(
+                instance.url,
+                api_key,
+                instance.kind,
+                config.page_size.unwrap_or(Api::DEFAULT_PAGE_SIZE),
+                config.diagnose_json,
+                filter.needs_parent(),
+                instance.http.as_ref().unwrap_or(&config.http),
+            )?,
+            rules: RuleSet::new(
+                rules,
+                removal,
+                instance
+                    .delete_file_reasons
+                    .unwrap_or_else(|| config.delete_file_reasons.clone()),
+                instance
+                    .private_trackers
+                    .unwrap_or_else(|| config.private_trackers.clone()),
+                instance.search,
+            ),
+            filter,
+            stall: instance.stall.unwrap_or_else(|| config.stall.clone()),
+            strikes: StrikePolicy {
+                strikes: instance.strikes.or(config.strikes).unwrap_or(1),
+                duration: instance.strike_duration.or(config.strike_duration),
+            },
+            stream_pages: config.stream_pages,
+            concurrency: config
+                .concurrency
+                .unwrap_or(Self::DEFAULT_CONCURRENCY)
+                .max(1),
+            search: instance.search,
+        })
+    }
+}
+
+/// Everything remembered between checks
+struct State {
+    stalls: StallTracker,
+    strikes: StrikeStore,
+    history: History,
+    /// Set once the tool is asked to shut down, so a check starts nothing new
+    shutdown: watch::Receiver<bool>,
+}
+
+impl State {
+    fn stopping(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+}
+
+/// Outcome of checking a single instance
+#[derive(Default)]
+struct Summary {
+    failed: usize,
+    skipped: usize,
+    removed: usize,
+    errors: usize,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    const BAD_STATUS: &str = "warning";
     let cli = Cli::parse();
-    let api = Api::new(cli.url, cli.api_key, cli.radarr, cli.skip_redownload);
-    let records = api.get_queue().await?.get_records();
+    let mut config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let state_dir = cli
+        .state_dir
+        .or(config.state_dir.take())
+        .unwrap_or_else(|| PathBuf::from("."));
+    let history = History::new(state_dir.join("actions.jsonl"));
+    config.page_size = cli.page_size.or(config.page_size);
+    config.stream_pages |= cli.stream_pages;
+    config.diagnose_json |= cli.diagnose_json;
+    config.concurrency = cli.concurrency.or(config.concurrency);
+    if !cli.delete_file_reasons.is_empty() {
+        config.delete_file_reasons = cli.delete_file_reasons;
+    }
+
+    if cli.history {
+        return print_history(&history);
+    }
+
+    let mut instances: Vec<Instance> = std::mem::take(&mut config.instances)
+        .into_iter()
+        .map(|instance| Instance::new(instance, &config))
+        .collect::<Result<_>>()?;
+    if let Some(url) = cli.url {
+        // A key file takes precedence over a key from the environment
+        let (api_key, api_key_file) = match cli.api_key_file {
+            Some(path) => (None, Some(path)),
+            None => (cli.api_key, None),
+        };
+        ensure!(
+            api_key.is_some() || api_key_file.is_some(),
+            "No API key given. Pass it as an argument, with --api-key-file or in ARR_API_KEY"
+        );
+        let instance = InstanceConfig {
+            name: url.clone(),
+            kind: if cli.radarr {
+                Some(Kind::Radarr)
+            } else {
+                cli.kind
+            },
+            url,
+            api_key,
+            api_key_file,
+            skip_redownload: cli.skip_redownload,
+            search: cli.search,
+            ..Default::default()
+        };
+        ensure!(
+            instances.iter().all(|other| other.name != instance.name),
+            "Instance name {} is already used in the config file",
+            instance.name
+        );
+        instances.push(Instance::new(instance, &config)?);
+    }
+    ensure!(!instances.is_empty(), "No instances to check");
+
+    // Only save strikes when some instance actually holds records back
+    let strikes_path = instances
+        .iter()
+        .any(|instance| instance.strikes.is_enabled())
+        .then(|| state_dir.join("strikes.json"));
+    // Likewise for progress, when some stall threshold compares against an earlier check
+    let stalls_path = instances
+        .iter()
+        .any(|instance| instance.stall.needs_history())
+        .then(|| state_dir.join("stalls.json"));
+    let mut state = State {
+        stalls: StallTracker::load(stalls_path)?,
+        strikes: StrikeStore::load(strikes_path)?,
+        history,
+        shutdown: watch::channel(false).1,
+    };
+
+    connect_all(&instances).await?;
+
+    match cli.interval {
+        Some(interval) => watch(&mut instances, &mut state, interval, cli.dry_run).await,
+        None => run_all(&mut instances, &mut state, cli.dry_run).await,
+    }
+}
+
+/// Detect every instance before the first check, so one that can't be handled stops the run right away.
+/// Instances that can't be reached are only reported, each check tries them again.
+async fn connect_all(instances: &[Instance]) -> Result<()> {
+    for instance in instances {
+        if let Err(e) = instance.api.connect().await {
+            let error = instance.api.scrub(&format!("{:?}", e));
+            ensure!(
+                !Refused::caused(&e),
+                "Can't check {}: {}",
+                instance.name,
+                error
+            );
+            println!("Failed to connect to {}: {}", instance.name, error);
+        }
+    }
+    Ok(())
+}
+
+/// Run a check every `interval` until SIGINT or SIGTERM is received.
+/// A failed check is reported and retried on the next interval, unless an instance can't be handled at all.
+async fn watch(
+    instances: &mut [Instance],
+    state: &mut State,
+    interval: Duration,
+    dry_run: bool,
+) -> Result<()> {
+    let mut signals = Signals::new()?;
+    let (shutdown_tx, mut shutdown) = watch::channel(false);
+    state.shutdown = shutdown.clone();
+    // The first signal lets the current step finish, a second one exits right away
+    tokio::spawn(async move {
+        if let Err(e) = signals.recv().await {
+            println!("Stopped listening for shutdown signals: {:?}", e);
+            return;
+        }
+        println!("Shutting down after the current step, send the signal again to stop now");
+        let _ = shutdown_tx.send(true);
+        if signals.recv().await.is_ok() {
+            println!("Stopping now");
+            std::process::exit(130);
+        }
+    });
+
+    println!(
+        "Checking queue every {}",
+        humantime::format_duration(interval)
+    );
+    loop {
+        if let Err(e) = run_all(instances, state, dry_run).await {
+            if Refused::caused(&e) {
+                return Err(e);
+            }
+            println!("Check failed: {:?}", e);
+        }
+
+        // A signal received during a check stops it before its next step and ends the loop here
+        if *shutdown.borrow() {
+            break;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            Ok(()) = shutdown.changed() => break,
+        }
+    }
+
+    println!("Shutting down");
+    Ok(())
+}
+
+/// SIGINT and SIGTERM, listened for from the start so a handler that can't be installed fails right away
+struct Signals {
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+}
+
+impl Signals {
+    fn new() -> Result<Self> {
+        #[cfg(unix)]
+        let terminate = {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            signal(SignalKind::terminate()).context("failed to listen for SIGTERM")?
+        };
 
-    // Filter out successfull records
-    let records: Vec<Record> = records
+        Ok(Self {
+            #[cfg(unix)]
+            terminate,
+        })
+    }
+
+    /// Wait for the next SIGINT or SIGTERM
+    async fn recv(&mut self) -> Result<()> {
+        #[cfg(unix)]
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = self.terminate.recv() => {}
+        }
+
+        #[cfg(not(unix))]
+        tokio::signal::ctrl_c().await?;
+
+        Ok(())
+    }
+}
+
+/// Check every instance in turn and print a summary grouped by instance.
+/// Fails only if every instance failed, so one unreachable server doesn't hide the others.
+async fn run_all(instances: &mut [Instance], state: &mut State, dry_run: bool) -> Result<()> {
+    let mut results = Vec::with_capacity(instances.len());
+    for instance in instances.iter_mut() {
+        if state.stopping() {
+            println!("Shutting down before checking {}", instance.name);
+            break;
+        }
+        println!("== {} ==", instance.name);
+        let result = run(instance, state, dry_run).await;
+        if let Err(e) = &result {
+            println!(
+                "Failed to check {}: {}",
+                instance.name,
+                instance.api.scrub(&format!("{:?}", e))
+            );
+        }
+        results.push((&instance.name, result));
+    }
+
+    if !dry_run {
+        if let Err(e) = state.stalls.save() {
+            println!("Failed to save stalls: {:?}", e);
+        }
+        if let Err(e) = state.strikes.save() {
+            println!("Failed to save strikes: {:?}", e);
+        }
+    }
+
+    println!("== Summary ==");
+    for (name, result) in &results {
+        match result {
+            Ok(summary) => println!(
+                "{}: {} failed, {} skipped, {} removed, {} errors",
+                name, summary.failed, summary.skipped, summary.removed, summary.errors
+            ),
+            Err(_) => println!("{}: check failed", name),
+        }
+    }
+
+    if let Some((name, _)) = results
+        .iter()
+        .find(|(_, result)| result.as_ref().is_err_and(Refused::caused))
+    {
+        bail!(Refused(format!("{} can't be checked", name)));
+    }
+    ensure!(
+        results.iter().any(|(_, result)| result.is_ok()),
+        "Every instance failed to check"
+    );
+    Ok(())
+}
+
+/// Fetch the queue once and act on every record a rule matched
+async fn run(instance: &mut Instance, state: &mut State, dry_run: bool) -> Result<Summary> {
+    let mut summary = Summary::default();
+    let result = check_queue(instance, state, dry_run, &mut summary).await;
+
+    // A check stopped to shut down may not have seen the whole queue
+    let completed = result.is_ok() && !state.stopping();
+    state.stalls.end_check(&instance.name, completed);
+    state.strikes.end_check(&instance.name, completed);
+    result.map(|()| summary)
+}
+
+async fn check_queue(
+    instance: &mut Instance,
+    state: &mut State,
+    dry_run: bool,
+    summary: &mut Summary,
+) -> Result<()> {
+    // Looked up every check, so tags added while watching are picked up
+    let tags = if instance.filter.uses_tags() {
+        instance.api.get_tags().await?
+    } else {
+        HashMap::new()
+    };
+
+    let mut seen = Seen::default();
+
+    if !instance.stream_pages {
+        let records = instance.api.get_queue().await?;
+        seen.add_media(&records);
+        process(instance, state, records, &tags, &mut seen, dry_run, summary).await;
+        return Ok(());
+    }
+
+    let first = instance.api.get_queue_page(1).await?;
+    let page_count = first.get_page_count();
+
+    // Removing a download removes its records on every page, before the pages they're on are reached.
+    // Searching needs all of them, so their media are looked up with a walk over the whole queue first.
+    if instance.search {
+        seen.add_media(&first.get_records());
+        for page in 2..=page_count {
+            let records = instance.api.get_queue_page(page).await?.get_records();
+            seen.add_media(&records);
+        }
+    }
+
+    // Walk from the last page to the first. Removing records only moves the ones after them forward,
+    // so no record moves to a page that was already walked before it was seen.
+    for page in (2..=page_count).rev() {
+        if state.stopping() {
+            return Ok(());
+        }
+        let records = instance.api.get_queue_page(page).await?.get_records();
+        process(instance, state, records, &tags, &mut seen, dry_run, summary).await;
+    }
+    if state.stopping() {
+        return Ok(());
+    }
+    process(
+        instance,
+        state,
+        first.get_records(),
+        &tags,
+        &mut seen,
+        dry_run,
+        summary,
+    )
+    .await;
+
+    Ok(())
+}
+
+/// What's known about the queue across the batches of one check
+#[derive(Default)]
+struct Seen {
+    /// Downloads already acted on, so one spread over several pages is only acted on once
+    handled: HashSet<String>,
+    /// Media of every record of each download, including records not acted on or on other pages
+    media: HashMap<String, Vec<Media>>,
+}
+
+impl Seen {
+    fn add_media(&mut self, records: &[Record]) {
+        for record in records
+            .iter()
+            .filter(|record| !record.download_id.is_empty())
+        {
+            let media = self.media.entry(record.download_key()).or_default();
+            let item = Media::of(record);
+            // A record that moved between pages while walking is seen twice
+            if !media.contains(&item) {
+                media.push(item);
+            }
+        }
+    }
+}
+
+/// The episode, movie, album or book of a queue record, and its season if it has one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Media {
+    id: i64,
+    season: Option<Season>,
+}
+
+impl Media {
+    fn of(record: &Record) -> Self {
+        Self {
+            id: record.media_id,
+            season: record.season,
+        }
+    }
+}
+
+/// Act on every record in a batch that a rule matched.
+/// Downloads in `seen.handled` were acted on with an earlier batch and are left alone, the others are added to it.
+async fn process(
+    instance: &mut Instance,
+    state: &mut State,
+    mut records: Vec<Record>,
+    tags: &HashMap<i64, String>,
+    seen: &mut Seen,
+    dry_run: bool,
+    summary: &mut Summary,
+) {
+    let api = &instance.api;
+
+    let stalled = state
+        .stalls
+        .check(&instance.name, &instance.stall, &records);
+    for record in &mut records {
+        if let Some(detail) = stalled.get(&record.id) {
+            println!("Stalled: {} ({})", record, detail);
+            record.reason = FailureReason::Stalled;
+        }
+    }
+
+    // Filter out downloads no rule wants to act on
+    let records: Vec<(Record, Decision)> = records
+        .into_iter()
+        .map(|record| {
+            let decision = instance.rules.decide(&record);
+            (record, decision)
+        })
+        .collect();
+    let (records, conflicts) = drop_ignored(records);
+    for (record, decision) in &conflicts {
+        println!(
+            "Ignored: {} and the rest of its download (rule: {})",
+            record, decision.rule
+        );
+        summary.skipped += 1;
+    }
+
+    // Leave protected records alone before anything is done to them, strikes included
+    let records: Vec<(Record, Decision)> = records
+        .into_iter()
+        .filter(
+            |(record, _)| match instance.filter.skip_reason(record, tags) {
+                Some(reason) => {
+                    println!("Skipped: {} ({})", record, reason);
+                    summary.skipped += 1;
+                    false
+                }
+                None => true,
+            },
+        )
+        .collect();
+
+    // Hold back records that haven't been bad for long enough yet
+    let keys: Vec<String> = records
+        .iter()
+        .map(|(record, _)| record.download_key())
+        .collect();
+    let current = state
+        .strikes
+        .update(&instance.name, keys.iter().map(String::as_str));
+    let records: Vec<(Record, Decision)> = records
         .into_iter()
-        .filter(|record| {
-            record.get_tracked_status() == BAD_STATUS || record.get_status() == BAD_STATUS
+        .filter(|(record, _)| {
+            let strike = &current[&record.download_key()];
+            let ready = instance.strikes.is_ready(strike);
+            if !ready {
+                println!(
+                    "Waiting: {} (strike {}/{})",
+                    record, strike.count, instance.strikes.strikes
+                );
+            }
+            ready
         })
         .collect();
 
-    // Delete failed records from files and queue
-    println!("Trying to delete {} records", records.len());
-    for record in &records {
-        if let Err(e) = api.delete_episode_file(record).await {
-            println!("Failed to delete: {:?}", e);
+    // A season pack is one download with a queue record per episode, so each download is acted on once
+    let downloads: Vec<Download> = group_downloads(&records, &seen.media)
+        .into_iter()
+        .filter(|download| {
+            let new = seen.handled.insert(download.records[0].download_key());
+            if !new {
+                println!("Already handled: {}", download);
+            }
+            new
+        })
+        .collect();
+    summary.failed += downloads.len();
+
+    // Without a post-import category, changing the category leaves the download where it is
+    for download in &downloads {
+        let record = download.records[0];
+        if download.decision.flags.change_category && record.has_post_import_category == Some(false)
+        {
+            println!(
+                "Warning: {} has no post-import category, so {} stays in its category",
+                record.download_client, download
+            );
+        }
+    }
+
+    if dry_run {
+        print_plan(api, &downloads, instance.concurrency, instance.search).await;
+        return;
+    }
+
+    // Delete failed downloads from files and queue.
+    // Files go first, so a download's files are always deleted before it leaves the queue.
+    println!("Trying to delete {} downloads", downloads.len());
+    // Once shutting down, downloads not started yet are left for the next run
+    let files: Vec<Option<FileOutcome>> = stream::iter(&downloads)
+        .take_while(|_| future::ready(!state.stopping()))
+        .map(|download| delete_files(api, download))
+        .buffered(instance.concurrency)
+        .collect()
+        .await;
+    if files.len() < downloads.len() {
+        println!(
+            "Shutting down, leaving {} downloads for the next run",
+            downloads.len() - files.len()
+        );
+    }
+    let downloads = &downloads[..files.len()];
+    let removed = remove_downloads(api, downloads, instance.concurrency).await;
+    let grabbed = if instance.search {
+        search_replacements(
+            api,
+            downloads,
+            &removed,
+            instance.concurrency,
+            &state.shutdown,
+            summary,
+        )
+        .await
+    } else {
+        HashMap::new()
+    };
+
+    let mut entries = Vec::with_capacity(downloads.len());
+    for (download, files) in downloads.iter().zip(files) {
+        let record = download.records[0];
+        let decision = download.decision;
+        let removed = removed.contains(&record.id);
+        if removed {
+            println!(
+                "Removed {} ({}, rule: {})",
+                download, record.reason, decision.rule
+            );
+            summary.removed += 1;
+        } else {
+            summary.errors += 1;
+        }
+        if files == Some(FileOutcome::Failed) {
+            summary.errors += 1;
+        }
+
+        // Whether each of the download's media got a new release, if they were searched for
+        let found: Option<Vec<bool>> = download
+            .media
+            .iter()
+            .map(|media| grabbed.get(&media.id).copied())
+            .collect();
+        let found = found.map(|found| found.iter().filter(|&&found| found).count());
+        match found {
+            Some(0) => println!("No replacement found for {}", download),
+            Some(n) if n == download.media.len() => {
+                println!("Grabbed a replacement for {}", download)
+            }
+            Some(n) => println!(
+                "Grabbed replacements for {} of {} items of {}",
+                n,
+                download.media.len(),
+                download
+            ),
+            None => {}
+        }
+
+        entries.push(Entry {
+            timestamp: Utc::now(),
+            instance: instance.name.clone(),
+            download_id: record.download_id.clone(),
+            record_id: record.id,
+            title: record.title.clone(),
+            indexer: record.indexer.clone(),
+            reason: record.reason,
+            rule: decision.rule.to_string(),
+            action: decision.action,
+            file_deleted: match &files {
+                Some(FileOutcome::Deleted) => Some(true),
+                Some(FileOutcome::Failed) => Some(false),
+                Some(FileOutcome::Kept(_)) | None => None,
+            },
+            file_kept: match files {
+                Some(FileOutcome::Kept(reason)) => Some(reason),
+                _ => None,
+            },
+            removed,
+            blocklisted: removed && decision.flags.blocklist,
+            grabbed: found.map(|n| n > 0),
+        });
+    }
+
+    if let Err(e) = state.history.append(&entries) {
+        println!("Failed to save history: {:?}", e);
+    }
+}
+
+/// The queue records of one download, several for a season pack
+struct Download<'a> {
+    records: Vec<&'a Record>,
+    /// Decision for the first record, which the whole download follows
+    decision: Decision<'a>,
+    /// What the download is for, from all of its records in the queue and not only the ones in `records`
+    media: Vec<Media>,
+}
+
+impl Download<'_> {
+    /// The season all of the download's media are episodes of, when it has several.
+    /// That's a multi-episode release as often as a season pack, see `whole_season`.
+    fn season(&self) -> Option<Season> {
+        let season = self.media[0].season?;
+        (self.media.len() > 1 && self.media.iter().all(|media| media.season == Some(season)))
+            .then_some(season)
+    }
+}
+
+/// The season a download covers every aired episode of, so it can be searched for as a season pack.
+/// A multi-episode release only covers part of its season, and searching the season would search all of it.
+async fn whole_season(api: &Api, download: &Download<'_>) -> Option<Season> {
+    let season = download.season()?;
+    match api.get_aired_episodes(season).await {
+        Ok(aired) => {
+            let episodes: HashSet<i64> = download.media.iter().map(|media| media.id).collect();
+            (!aired.is_empty() && aired.iter().all(|episode| episodes.contains(episode)))
+                .then_some(season)
+        }
+        Err(e) => {
+            println!(
+                "Failed to check whether {} is a season pack, searching for its episodes: {}",
+                download,
+                api.scrub(&format!("{:?}", e))
+            );
+            None
+        }
+    }
+}
+
+impl fmt::Display for Download<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.records.len() {
+            1 => write!(f, "{}", self.records[0]),
+            n => write!(f, "{} ({} records)", self.records[0], n),
+        }
+    }
+}
+
+/// A queue record and what to do with it
+type Decided<'a> = (Record, Decision<'a>);
+
+/// Leave out every download a rule ignores any record of, since removing one record removes the whole
+/// download. Returns the records left, and for each download that's only partly ignored its first ignored
+/// record, so the conflict can be reported.
+fn drop_ignored(records: Vec<Decided<'_>>) -> (Vec<Decided<'_>>, Vec<Decided<'_>>) {
+    let mut ignored: HashMap<String, usize> = HashMap::new();
+    for (index, (record, decision)) in records.iter().enumerate() {
+        if decision.action == Action::Ignore {
+            ignored.entry(record.download_key()).or_insert(index);
+        }
+    }
+    let conflicting: HashSet<String> = records
+        .iter()
+        .filter(|(_, decision)| decision.action != Action::Ignore)
+        .map(|(record, _)| record.download_key())
+        .filter(|key| ignored.contains_key(key))
+        .collect();
+
+    let mut kept = Vec::new();
+    let mut conflicts = Vec::new();
+    for (index, (record, decision)) in records.into_iter().enumerate() {
+        let key = record.download_key();
+        match ignored.get(&key) {
+            None => kept.push((record, decision)),
+            Some(&first) if first == index && conflicting.contains(&key) => {
+                conflicts.push((record, decision))
+            }
+            Some(_) => {}
+        }
+    }
+    (kept, conflicts)
+}
+
+/// Group records by download, keeping the queue's order.
+/// Records without a download id can't be matched up, so each is its own download.
+/// A download's media are taken from `media` when it's there, and from its records otherwise.
+fn group_downloads<'a>(
+    records: &'a [Decided<'a>],
+    media: &HashMap<String, Vec<Media>>,
+) -> Vec<Download<'a>> {
+    let mut downloads: Vec<Download> = Vec::new();
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    for (record, decision) in records {
+        let key = record.download_key();
+        if let Some(&index) = indexes.get(&key) {
+            downloads[index].records.push(record);
             continue;
+        }
+        indexes.insert(key, downloads.len());
+        downloads.push(Download {
+            records: vec![record],
+            decision: *decision,
+            media: Vec::new(),
+        });
+    }
+
+    for download in &mut downloads {
+        download.media = match media.get(&download.records[0].download_key()) {
+            Some(media) => media.clone(),
+            None => download
+                .records
+                .iter()
+                .map(|record| Media::of(record))
+                .collect(),
         };
     }
+    downloads
+}
 
-    for record in &records {
-        if api.delete_queue_record(record).await.is_err() {
-            continue;
+/// What happened to the files of a download whose action deletes them
+#[derive(Debug, PartialEq, Eq)]
+enum FileOutcome {
+    Deleted,
+    /// Not deleted because there's none or it's worth keeping, with why
+    Kept(String),
+    Failed,
+}
+
+/// Delete the download's files if the action asks for it and they're from its release.
+/// Returns what happened to them, or None when the action doesn't delete files.
+async fn delete_files(api: &Api, download: &Download<'_>) -> Option<FileOutcome> {
+    if download.decision.action != Action::DeleteFile {
+        return None;
+    }
+    let record = download.records[0];
+    let result = match api.get_download_files(&download.records).await {
+        Ok(Files::Delete(file_ids)) => api.delete_files(record, &file_ids).await,
+        Ok(Files::NoFile) => {
+            println!("No file to delete: {}", download);
+            return Some(FileOutcome::Kept("no file".to_string()));
+        }
+        Ok(Files::Keep(reason)) => {
+            println!("Kept file: {} ({})", download, reason);
+            return Some(FileOutcome::Kept(reason));
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => Some(FileOutcome::Deleted),
+        Err(e) => {
+            println!("Failed to delete: {}", api.scrub(&format!("{:?}", e)));
+            Some(FileOutcome::Failed)
+        }
+    }
+}
+
+/// Group downloads by the flags they're removed with, so each group can be removed with one request.
+/// Removing one record of a download removes all of it, so only the first record is kept.
+fn removal_groups<'a>(downloads: &[Download<'a>]) -> BTreeMap<RemovalFlags, Vec<&'a Record>> {
+    let mut groups: BTreeMap<RemovalFlags, Vec<&Record>> = BTreeMap::new();
+    for download in downloads {
+        groups
+            .entry(download.decision.flags)
+            .or_default()
+            .push(download.records[0]);
+    }
+    groups
+}
+
+/// Remove downloads from the queue with one request for each set of flags.
+/// If a bulk request fails its records are removed one at a time instead.
+/// Returns the ids of the first records of the downloads that were removed.
+async fn remove_downloads(
+    api: &Api,
+    downloads: &[Download<'_>],
+    concurrency: usize,
+) -> HashSet<i64> {
+    let mut removed = HashSet::new();
+    for (flags, group) in removal_groups(downloads) {
+        if group.len() > 1 {
+            match api.delete_queue_records(&group, flags).await {
+                Ok(()) => {
+                    removed.extend(group.iter().map(|record| record.id));
+                    continue;
+                }
+                Err(e) => println!(
+                    "Failed to remove {} records at once, removing them one at a time: {}",
+                    group.len(),
+                    api.scrub(&format!("{:?}", e))
+                ),
+            }
+        }
+
+        let ids: Vec<Option<i64>> = stream::iter(group)
+            .map(|record| async move {
+                match api.delete_queue_record(record, flags).await {
+                    Ok(()) => Some(record.id),
+                    Err(e) => {
+                        println!("Failed to remove: {}", api.scrub(&format!("{:?}", e)));
+                        None
+                    }
+                }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+        removed.extend(ids.into_iter().flatten());
+    }
+
+    removed
+}
+
+/// Search for new releases of the removed downloads' media, then check the history for which ones got one.
+/// Season packs are searched for as a whole season, everything else, multi-episode releases included,
+/// with one search for all its media.
+/// Returns whether each searched media got a new release, leaving out media whose search didn't complete.
+async fn search_replacements(
+    api: &Api,
+    downloads: &[Download<'_>],
+    removed: &HashSet<i64>,
+    concurrency: usize,
+    shutdown: &watch::Receiver<bool>,
+    summary: &mut Summary,
+) -> HashMap<i64, bool> {
+    let to_search: Vec<&Download> = downloads
+        .iter()
+        .filter(|download| removed.contains(&download.records[0].id))
+        .collect();
+    let whole_seasons: Vec<Option<Season>> = stream::iter(&to_search)
+        .map(|download| whole_season(api, download))
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    let mut media_ids = BTreeSet::new();
+    let mut seasons: BTreeMap<Season, Vec<i64>> = BTreeMap::new();
+    for (download, whole_season) in to_search.iter().zip(whole_seasons) {
+        let ids = download.media.iter().map(|media| media.id);
+        match whole_season {
+            Some(season) => seasons.entry(season).or_default().extend(ids),
+            None => media_ids.extend(ids),
+        }
+    }
+
+    if *shutdown.borrow() {
+        println!("Shutting down, skipping the searches for replacements");
+        return HashMap::new();
+    }
+
+    // Grabs are looked up from when the first search was queued, by the instance's own clock
+    let started = Utc::now();
+    let mut since = None;
+    let mut searched = Vec::new();
+    if !media_ids.is_empty() {
+        let media_ids: Vec<i64> = media_ids.into_iter().collect();
+        println!("Searching for {} replacements", media_ids.len());
+        if let Some(command) = search_completed(api, api.search(&media_ids).await, summary) {
+            since = since.or(command.queued);
+            searched.extend(media_ids);
+        }
+    }
+    for (season, media_ids) in seasons {
+        if *shutdown.borrow() {
+            println!("Shutting down, skipping the remaining searches");
+            break;
+        }
+        println!(
+            "Searching for season {} of series {}",
+            season.season_number, season.series_id
+        );
+        if let Some(command) = search_completed(api, api.search_season(season).await, summary) {
+            since = since.or(command.queued);
+            searched.extend(media_ids);
+        }
+    }
+    if searched.is_empty() {
+        return HashMap::new();
+    }
+
+    // Grabbing the removed release again, when it wasn't blocklisted, isn't a replacement
+    let removed_downloads: HashSet<&str> = downloads
+        .iter()
+        .filter(|download| removed.contains(&download.records[0].id))
+        .map(|download| download.records[0].download_id.as_str())
+        .filter(|download_id| !download_id.is_empty())
+        .collect();
+    let since = since.unwrap_or_else(|| started.to_rfc3339_opts(SecondsFormat::Secs, true));
+    let grabbed: HashSet<i64> = match api.get_grabs_since(&since).await {
+        Ok(grabs) => replacements(&grabs, &searched, &removed_downloads)
+            .map(|grab| {
+                println!("Grabbed {}", grab.title);
+                grab.media_id
+            })
+            .collect(),
+        Err(e) => {
+            println!(
+                "Failed to check the history for replacements: {}",
+                api.scrub(&format!("{:?}", e))
+            );
+            summary.errors += 1;
+            return HashMap::new();
+        }
+    };
+    searched
+        .into_iter()
+        .map(|media_id| (media_id, grabbed.contains(&media_id)))
+        .collect()
+}
+
+/// The grabs that replace a removed download: for media that was searched for, and not of a removed release
+fn replacements<'a>(
+    grabs: &'a [Grab],
+    searched: &'a [i64],
+    removed_downloads: &'a HashSet<&str>,
+) -> impl Iterator<Item = &'a Grab> {
+    grabs.iter().filter(|grab| {
+        searched.contains(&grab.media_id) && !removed_downloads.contains(grab.download_id.as_str())
+    })
+}
+
+/// Report a search that failed or didn't complete, returning the command if it completed
+fn search_completed(api: &Api, result: Result<Command>, summary: &mut Summary) -> Option<Command> {
+    match result {
+        Ok(command) if command.status == CommandStatus::Completed => Some(command),
+        Ok(command) => {
+            println!(
+                "Search {}: {}",
+                command.status,
+                command.message.unwrap_or_default()
+            );
+            summary.errors += 1;
+            None
+        }
+        Err(e) => {
+            println!("Failed to search: {}", api.scrub(&format!("{:?}", e)));
+            summary.errors += 1;
+            None
+        }
+    }
+}
+
+/// Print what a real run would delete
+async fn print_plan(api: &Api, downloads: &[Download<'_>], concurrency: usize, search: bool) {
+    println!("Dry run: would remove {} downloads", downloads.len());
+    let files: Vec<String> = stream::iter(downloads)
+        .map(|download| async move {
+            if download.decision.action != Action::DeleteFile {
+                return String::new();
+            }
+            match api.get_download_files(&download.records).await {
+                Ok(Files::Delete(file_ids)) => format!("delete files {:?}, ", file_ids),
+                Ok(Files::NoFile) => "no file to delete, ".to_string(),
+                Ok(Files::Keep(reason)) => format!("keep file ({}), ", reason),
+                Err(e) => format!("failed to look up files ({}), ", api.scrub(&e.to_string())),
+            }
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+    let seasons: Vec<Option<Season>> = stream::iter(downloads)
+        .map(|download| async move {
+            match search {
+                true => whole_season(api, download).await,
+                false => None,
+            }
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    for ((download, file), season) in downloads.iter().zip(files).zip(seasons) {
+        let search = match (search, season) {
+            (false, _) => "",
+            (true, Some(_)) => ", then search for the season",
+            (true, None) => ", then search",
         };
+        println!(
+            "  {} -> {}remove from queue with {}{} ({}, rule: {})",
+            download,
+            file,
+            download.decision.flags,
+            search,
+            download.records[0].reason,
+            download.decision.rule
+        );
     }
+}
 
+/// Print every recorded action, oldest first
+fn print_history(history: &History) -> Result<()> {
+    for entry in history.load()? {
+        let file = match (entry.file_deleted, &entry.file_kept) {
+            (Some(true), _) => ", file deleted".to_string(),
+            (Some(false), _) => ", file delete failed".to_string(),
+            (None, Some(reason)) => format!(", file kept ({})", reason),
+            (None, None) => String::new(),
+        };
+        let removed = match (entry.removed, entry.blocklisted) {
+            (true, true) => "removed and blocklisted",
+            (true, false) => "removed",
+            (false, _) => "remove failed",
+        };
+        let grabbed = match entry.grabbed {
+            Some(true) => ", replacement grabbed",
+            Some(false) => ", no replacement found",
+            None => "",
+        };
+        println!(
+            "{} [{}] {}: {} from {} ({}, rule: {}) -> {}{}{}",
+            entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            entry.instance,
+            entry.record_id,
+            entry.title,
+            entry.indexer,
+            entry.reason,
+            entry.rule,
+            removed,
+            file,
+            grabbed
+        );
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use api::HttpConfig;
+
+    const BLOCKLIST: RemovalFlags = RemovalFlags {
+        remove_from_client: false,
+        blocklist: true,
+        change_category: false,
+        skip_redownload: false,
+    };
+
+    fn decision(rule: &str) -> Decision<'_> {
+        Decision {
+            action: Action::Blocklist,
+            rule,
+            flags: BLOCKLIST,
+        }
+    }
+
+    /// Requests received by a `serve` stub, as method and path
+    type Requests = Arc<Mutex<Vec<String>>>;
+
+    /// Answer every request to a local Sonarr stub with `respond`, which gets the method and path and
+    /// returns the status code and body. Returns the stub's url and the requests it received.
+    async fn serve(respond: fn(&str, &str) -> (u16, &'static str)) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let received = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let head = read_request(&mut stream).await;
+                let mut request_line = head.split_whitespace();
+                let method = request_line.next().unwrap_or_default().to_string();
+                let path = request_line.next().unwrap_or_default();
+                let path = path.split('?').next().unwrap_or_default().to_string();
+
+                let (status, body) = match path.as_str() {
+                    "/api/v3/system/status" => (200, r#"{"appName":"Sonarr","version":"4.0.0.0"}"#),
+                    _ => {
+                        received
+                            .lock()
+                            .unwrap()
+                            .push(format!("{} {}", method, path));
+                        respond(&method, &path)
+                    }
+                };
+                let response = format!(
+                    "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    /// Read a request up to the end of its body, returning its head
+    async fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            let Some(end) = text.find("\r\n\r\n") else {
+                if read == 0 {
+                    return text;
+                }
+                continue;
+            };
+            let head = text[..end].to_string();
+            let length: usize = head
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse().ok())?
+                })
+                .unwrap_or(0);
+            if read == 0 || request.len() >= end + 4 + length {
+                return head;
+            }
+        }
+    }
+
+    fn stub_api(url: String) -> Api {
+        Api::new(
+            url,
+            "key".to_string(),
+            Some(Kind::Sonarr),
+            Api::DEFAULT_PAGE_SIZE,
+            false,
+            false,
+            &HttpConfig::default(),
+        )
+        .unwrap()
+    }
+
+    fn episode(id: i64, download_id: &str, season_number: i64) -> Record {
+        let mut record = Record::example(id, download_id);
+        record.season = Some(Season {
+            series_id: 1,
+            season_number,
+        });
+        record
+    }
+
+    #[test]
+    fn groups_records_by_download() {
+        let records = vec![
+            (episode(1, "pack", 1), decision("first")),
+            (episode(2, "single", 1), decision("first")),
+            (episode(3, "pack", 1), decision("second")),
+        ];
+        let downloads = group_downloads(&records, &HashMap::new());
+
+        assert_eq!(downloads.len(), 2);
+        let ids: Vec<i64> = downloads[0].records.iter().map(|r| r.id).collect();
+        assert_eq!(ids, [1, 3]);
+        assert_eq!(downloads[0].decision.rule, "first");
+        assert_eq!(
+            downloads[0].season(),
+            Some(Season {
+                series_id: 1,
+                season_number: 1
+            })
+        );
+        assert_eq!(downloads[1].season(), None);
+    }
+
+    #[test]
+    fn ignoring_one_record_keeps_its_whole_download() {
+        let ignore = Decision {
+            action: Action::Ignore,
+            ..decision("leave upgrades alone")
+        };
+        let records = vec![
+            (episode(1, "pack", 1), decision("warning status")),
+            (episode(2, "pack", 1), ignore),
+            (episode(3, "pack", 1), ignore),
+            (episode(4, "single", 1), decision("warning status")),
+            (episode(5, "untouched", 1), ignore),
+        ];
+        let (kept, conflicts) = drop_ignored(records);
+
+        let kept: Vec<i64> = kept.iter().map(|(record, _)| record.id).collect();
+        assert_eq!(kept, [4]);
+        // Reported once for the pack, and not for a download that's ignored as a whole
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].0.id, 2);
+        assert_eq!(conflicts[0].1.rule, "leave upgrades alone");
+    }
+
+    #[test]
+    fn takes_media_from_every_page() {
+        let mut seen = Seen::default();
+        // The pack's first two episodes are on one page, the third on the next
+        seen.add_media(&[episode(1, "pack", 1), episode(2, "pack", 1)]);
+        seen.add_media(&[
+            episode(3, "pack", 1),
+            episode(2, "pack", 1),
+            episode(4, "", 1),
+        ]);
+
+        let records = vec![(episode(3, "pack", 1), decision("rule"))];
+        let downloads = group_downloads(&records, &seen.media);
+        let media: Vec<i64> = downloads[0].media.iter().map(|media| media.id).collect();
+        assert_eq!(media, [1, 2, 3]);
+        assert_eq!(
+            downloads[0].season(),
+            Some(Season {
+                series_id: 1,
+                season_number: 1
+            })
+        );
+        // Records without a download id are never part of another download
+        assert_eq!(seen.media.len(), 1);
+    }
+
+    #[test]
+    fn never_groups_records_without_a_download_id() {
+        let records = vec![
+            (episode(1, "", 1), decision("rule")),
+            (episode(2, "", 1), decision("rule")),
+        ];
+        assert_eq!(group_downloads(&records, &HashMap::new()).len(), 2);
+    }
+
+    #[test]
+    fn groups_downloads_by_removal_flags() {
+        let keep_seeding = RemovalFlags {
+            remove_from_client: false,
+            change_category: true,
+            ..BLOCKLIST
+        };
+        let records = vec![
+            (episode(1, "pack", 1), decision("rule")),
+            (episode(2, "pack", 1), decision("rule")),
+            (
+                episode(3, "private", 1),
+                Decision {
+                    flags: keep_seeding,
+                    ..decision("rule")
+                },
+            ),
+            (episode(4, "single", 1), decision("rule")),
+        ];
+        let downloads = group_downloads(&records, &HashMap::new());
+        let groups = removal_groups(&downloads);
+
+        let ids: Vec<(RemovalFlags, Vec<i64>)> = groups
+            .into_iter()
+            .map(|(flags, group)| (flags, group.iter().map(|r| r.id).collect()))
+            .collect();
+        // Only the pack's first record is sent, removing it removes the whole pack
+        assert_eq!(ids, [(BLOCKLIST, vec![1, 4]), (keep_seeding, vec![3])]);
+    }
+
+    #[tokio::test]
+    async fn removes_one_at_a_time_when_the_bulk_request_fails() {
+        let (url, requests) = serve(|method, path| match (method, path) {
+            ("DELETE", "/api/v3/queue/bulk") => (500, ""),
+            ("DELETE", "/api/v3/queue/2") => (404, ""),
+            _ => (200, ""),
+        })
+        .await;
+        let api = stub_api(url);
+        let records = vec![
+            (episode(1, "a", 1), decision("rule")),
+            (episode(2, "b", 1), decision("rule")),
+            (episode(3, "c", 1), decision("rule")),
+        ];
+        let downloads = group_downloads(&records, &HashMap::new());
+
+        let removed = remove_downloads(&api, &downloads, 2).await;
+        assert_eq!(removed, HashSet::from([1, 3]));
+
+        let mut requests = requests.lock().unwrap().clone();
+        requests[1..].sort();
+        assert_eq!(
+            requests,
+            [
+                "DELETE /api/v3/queue/bulk",
+                "DELETE /api/v3/queue/1",
+                "DELETE /api/v3/queue/2",
+                "DELETE /api/v3/queue/3",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn removes_a_group_with_one_request() {
+        let (url, requests) = serve(|_, _| (200, "")).await;
+        let api = stub_api(url);
+        let records = vec![
+            (episode(1, "a", 1), decision("rule")),
+            (episode(2, "b", 1), decision("rule")),
+            (
+                episode(3, "c", 1),
+                Decision {
+                    flags: RemovalFlags {
+                        blocklist: false,
+                        ..BLOCKLIST
+                    },
+                    ..decision("rule")
+                },
+            ),
+        ];
+        let downloads = group_downloads(&records, &HashMap::new());
+
+        let removed = remove_downloads(&api, &downloads, 2).await;
+        assert_eq!(removed, HashSet::from([1, 2, 3]));
+        // A group of one doesn't need the bulk endpoint
+        assert_eq!(
+            *requests.lock().unwrap(),
+            ["DELETE /api/v3/queue/3", "DELETE /api/v3/queue/bulk"]
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_searches_once_shutting_down() {
+        let (url, requests) = serve(|_, _| (200, "")).await;
+        let api = stub_api(url);
+        let records = vec![
+            (episode(1, "a", 1), decision("rule")),
+            (episode(2, "b", 2), decision("rule")),
+        ];
+        let downloads = group_downloads(&records, &HashMap::new());
+        let (_shutdown_tx, shutdown) = watch::channel(true);
+
+        let grabbed = search_replacements(
+            &api,
+            &downloads,
+            &HashSet::from([1, 2]),
+            2,
+            &shutdown,
+            &mut Summary::default(),
+        )
+        .await;
+        assert!(grabbed.is_empty());
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    fn grab(media_id: i64, download_id: &str) -> Grab {
+        Grab {
+            media_id,
+            title: format!("Release {}", media_id),
+            download_id: download_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn counts_only_new_grabs_of_searched_media() {
+        let grabs = vec![
+            grab(1, "new"),
+            // The removed release grabbed again, because it wasn't blocklisted
+            grab(2, "removed"),
+            // Grabbed for media that wasn't searched for, like by an RSS sync
+            grab(3, "other"),
+            // A grab without a download id can't be told apart, so it counts
+            grab(4, ""),
+        ];
+        let removed_downloads = HashSet::from(["removed"]);
+
+        let media_ids: Vec<i64> = replacements(&grabs, &[1, 2, 4], &removed_downloads)
+            .map(|grab| grab.media_id)
+            .collect();
+        assert_eq!(media_ids, [1, 4]);
+    }
+
+    #[tokio::test]
+    async fn keeps_files_not_from_the_failed_release() {
+        let (url, requests) = serve(|method, path| match (method, path) {
+            ("GET", "/api/v3/episode/1") => (
+                200,
+                r#"{"id":1,"episodeFileId":10,"episodeFile":{"id":10,"sceneName":"Release 1"}}"#,
+            ),
+            ("GET", "/api/v3/episode/2") => (
+                200,
+                r#"{"id":2,"episodeFileId":20,"episodeFile":{"id":20,"sceneName":"Better.Release"}}"#,
+            ),
+            ("GET", _) => (200, r#"{"id":3}"#),
+            _ => (200, ""),
+        })
+        .await;
+        let api = stub_api(url);
+        let delete_file = Decision {
+            action: Action::DeleteFile,
+            ..decision("rule")
+        };
+        let records = vec![
+            (episode(1, "a", 1), delete_file),
+            (episode(2, "b", 1), delete_file),
+            (episode(3, "c", 1), delete_file),
+            (episode(4, "d", 1), decision("rule")),
+        ];
+        let downloads = group_downloads(&records, &HashMap::new());
+
+        let mut outcomes = Vec::new();
+        for download in &downloads {
+            outcomes.push(delete_files(&api, download).await);
+        }
+        assert_eq!(
+            outcomes,
+            [
+                Some(FileOutcome::Deleted),
+                Some(FileOutcome::Kept(
+                    "file 20: it was imported from Better.Release".to_string()
+                )),
+                Some(FileOutcome::Kept("no file".to_string())),
+                None,
+            ]
+        );
+        assert!(requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.starts_with("DELETE"))
+            .eq(["DELETE /api/v3/episodefile/10"]));
+    }
+
+    #[test]
+    fn download_across_seasons_has_no_season() {
+        let records = vec![
+            (episode(1, "complete", 1), decision("rule")),
+            (episode(2, "complete", 2), decision("rule")),
+        ];
+        let downloads = group_downloads(&records, &HashMap::new());
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(downloads[0].season(), None);
+    }
+}
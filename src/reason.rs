@@ -0,0 +1,242 @@
+use core::fmt;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Why a download is stuck, classified from its status messages and error message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// The release isn't better than the file already on disk
+    NotAnUpgrade,
+    /// The only video found is a sample
+    Sample,
+    /// Nothing in the download could be imported
+    NoEligibleFiles,
+    /// The download contains an executable or other dangerous file
+    DangerousFile,
+    /// The release or its files couldn't be parsed
+    UnableToParse,
+    /// The release couldn't be matched to a series or movie
+    UnknownMedia,
+    /// The files were already imported
+    AlreadyImported,
+    /// Not enough free space to import
+    DiskFull,
+    /// The download client reported an error
+    DownloadFailed,
+    /// The download stopped progressing or is too slow
+    Stalled,
+    /// Messages were reported but none are recognised
+    Other,
+    /// No messages were reported
+    NoMessage,
+}
+
+/// How a message is recognised as a reason
+enum Pattern {
+    /// The message contains this fragment
+    Contains(&'static str),
+    /// The message is exactly this, for short rejections that would otherwise match release names
+    Equals(&'static str),
+}
+
+impl Pattern {
+    /// Whether a lowercased message matches
+    fn matches(&self, message: &str) -> bool {
+        match self {
+            Pattern::Contains(fragment) => message.contains(fragment),
+            Pattern::Equals(text) => message.trim() == *text,
+        }
+    }
+}
+
+impl FailureReason {
+    /// Message patterns for each reason, checked in order so the most serious reason wins
+    const PATTERNS: &'static [(FailureReason, &'static [Pattern])] = &[
+        (
+            FailureReason::DangerousFile,
+            &[
+                Pattern::Contains("potentially dangerous file"),
+                Pattern::Contains("executable file"),
+            ],
+        ),
+        (
+            FailureReason::DiskFull,
+            &[Pattern::Contains("not enough free space")],
+        ),
+        // The rejection is just "Sample", while titles and album names can contain the word
+        (FailureReason::Sample, &[Pattern::Equals("sample")]),
+        (
+            FailureReason::NotAnUpgrade,
+            &[
+                Pattern::Contains("not an upgrade"),
+                Pattern::Contains("not a custom format upgrade"),
+            ],
+        ),
+        (
+            FailureReason::NoEligibleFiles,
+            &[Pattern::Contains("no files found")],
+        ),
+        (
+            FailureReason::UnableToParse,
+            &[Pattern::Contains("unable to parse")],
+        ),
+        (
+            FailureReason::UnknownMedia,
+            &[
+                Pattern::Contains("unknown series"),
+                Pattern::Contains("unknown movie"),
+                Pattern::Contains("matched to series by id"),
+                Pattern::Contains("matched to movie by id"),
+            ],
+        ),
+        (
+            FailureReason::AlreadyImported,
+            &[Pattern::Contains("already imported")],
+        ),
+    ];
+
+    pub fn classify(status_messages: &[String], error_message: Option<&str>) -> Self {
+        let messages: Vec<String> = status_messages
+            .iter()
+            .map(|message| message.to_lowercase())
+            .chain(error_message.map(str::to_lowercase))
+            .collect();
+
+        let known = Self::PATTERNS.iter().find(|(_, patterns)| {
+            messages
+                .iter()
+                .any(|message| patterns.iter().any(|pattern| pattern.matches(message)))
+        });
+
+        match known {
+            Some((reason, _)) => *reason,
+            None if error_message.is_some() => Self::DownloadFailed,
+            None if !messages.is_empty() => Self::Other,
+            None => Self::NoMessage,
+        }
+    }
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            Self::NotAnUpgrade => "not an upgrade",
+            Self::Sample => "sample",
+            Self::NoEligibleFiles => "no eligible files",
+            Self::DangerousFile => "dangerous file",
+            Self::UnableToParse => "unable to parse",
+            Self::UnknownMedia => "unknown media",
+            Self::AlreadyImported => "already imported",
+            Self::DiskFull => "disk full",
+            Self::DownloadFailed => "download failed",
+            Self::Stalled => "stalled",
+            Self::Other => "other",
+            Self::NoMessage => "no message",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(messages: &[&str]) -> FailureReason {
+        let messages: Vec<String> = messages.iter().map(|m| m.to_string()).collect();
+        FailureReason::classify(&messages, None)
+    }
+
+    #[test]
+    fn classifies_import_messages() {
+        assert_eq!(
+            classify(&["Not an upgrade for existing episode file(s). Existing quality: WEBDL-1080p. New Quality WEBDL-720p."]),
+            FailureReason::NotAnUpgrade
+        );
+        assert_eq!(
+            classify(&["Not a Custom Format upgrade for existing movie file(s). New: [] (0) do not improve on Existing: [x265] (50)"]),
+            FailureReason::NotAnUpgrade
+        );
+        assert_eq!(classify(&["Sample"]), FailureReason::Sample);
+        assert_eq!(
+            classify(&["No files found are eligible for import in /downloads/Show.S01E01.720p"]),
+            FailureReason::NoEligibleFiles
+        );
+        assert_eq!(
+            classify(&["Unable to parse file"]),
+            FailureReason::UnableToParse
+        );
+        assert_eq!(
+            classify(&["Found executable file with extension: '.exe'"]),
+            FailureReason::DangerousFile
+        );
+        assert_eq!(
+            classify(&["Caution: Found potentially dangerous file with extension: .lnk"]),
+            FailureReason::DangerousFile
+        );
+        assert_eq!(
+            classify(&["Not enough free space to import Show.S01E01.mkv"]),
+            FailureReason::DiskFull
+        );
+    }
+
+    #[test]
+    fn most_serious_reason_wins() {
+        assert_eq!(
+            classify(&[
+                "Not an upgrade for existing episode file(s)",
+                "Caution: Found potentially dangerous file with extension: .exe",
+            ]),
+            FailureReason::DangerousFile
+        );
+    }
+
+    #[test]
+    fn undecided_sample_check_is_not_a_sample() {
+        assert_eq!(
+            classify(&["Unable to determine if file is a sample"]),
+            FailureReason::Other
+        );
+        assert_eq!(
+            classify(&[
+                "Unable to determine if file is a sample",
+                "Not an upgrade for existing episode file(s)",
+            ]),
+            FailureReason::NotAnUpgrade
+        );
+    }
+
+    #[test]
+    fn names_containing_sample_are_not_samples() {
+        assert_eq!(
+            classify(&["Artist - Samples (2019) [FLAC]"]),
+            FailureReason::Other
+        );
+        assert_eq!(
+            classify(&["Show.S01E01.1080p.WEB-DL-GRP.sample.mkv"]),
+            FailureReason::Other
+        );
+        assert_eq!(
+            classify(&[
+                "Show.S01E01.sample.mkv",
+                "Not an upgrade for existing episode file(s)",
+            ]),
+            FailureReason::NotAnUpgrade
+        );
+    }
+
+    #[test]
+    fn falls_back_on_what_was_reported() {
+        assert_eq!(
+            FailureReason::classify(&[], Some("The download is missing files")),
+            FailureReason::DownloadFailed
+        );
+        assert_eq!(
+            classify(&["Something nobody has seen before"]),
+            FailureReason::Other
+        );
+        assert_eq!(classify(&[]), FailureReason::NoMessage);
+    }
+}
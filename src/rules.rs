@@ -0,0 +1,538 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    api::{Record, RemovalFlags},
+    config::deserialize_duration,
+    reason::FailureReason,
+    status::{
+        deserialize_known, DownloadProtocol, QueueStatus, TrackedDownloadState,
+        TrackedDownloadStatus,
+    },
+};
+
+/// What to do with a queue record matched by a rule
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Leave the record in the queue
+    #[default]
+    Ignore,
+    /// Remove the record from the queue without blocklisting the release
+    Remove,
+    /// Remove the record from the queue and blocklist the release
+    Blocklist,
+    /// Delete the media's file, then remove the record and blocklist the release
+    DeleteFile,
+}
+
+/// Matches queue records on any combination of fields; every field set on the rule has to match.
+/// Statuses and protocol have to be values the API sends, other string fields are compared case-insensitively.
+/// `status_message` and `error_message` are regexes.
+///
+/// ```toml
+/// [[rules]]
+/// name = "nothing to import"
+/// tracked_download_state = "importBlocked"
+/// reason = ["no_eligible_files", "not_an_upgrade"]
+/// action = "ignore"
+/// ```
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    pub action: Action,
+    #[serde(default, deserialize_with = "deserialize_known")]
+    pub status: Option<QueueStatus>,
+    #[serde(default, deserialize_with = "deserialize_known")]
+    pub tracked_download_status: Option<TrackedDownloadStatus>,
+    #[serde(default, deserialize_with = "deserialize_known")]
+    pub tracked_download_state: Option<TrackedDownloadState>,
+    /// Matches if the record's classified failure reason is any of these
+    pub reason: Option<Vec<FailureReason>>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub status_message: Option<Regex>,
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub error_message: Option<Regex>,
+    #[serde(default, deserialize_with = "deserialize_known")]
+    pub protocol: Option<DownloadProtocol>,
+    pub indexer: Option<String>,
+    pub download_client: Option<String>,
+    pub quality: Option<String>,
+    pub min_custom_format_score: Option<i64>,
+    pub max_custom_format_score: Option<i64>,
+    /// Size in bytes
+    pub min_size: Option<i64>,
+    /// Size in bytes
+    pub max_size: Option<i64>,
+    /// Time since the release was added to the queue -- Ex: 6h
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub min_age: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub max_age: Option<Duration>,
+    /// Flags used when removing matched records, over the instance's
+    #[serde(default)]
+    pub removal: RemovalConfig,
+}
+
+impl Rule {
+    pub fn matches(&self, record: &Record) -> bool {
+        fn same(expected: &Option<String>, actual: &str) -> bool {
+            expected
+                .as_ref()
+                .is_none_or(|expected| expected.eq_ignore_ascii_case(actual))
+        }
+
+        fn is<T: PartialEq>(expected: &Option<T>, actual: &T) -> bool {
+            expected.as_ref().is_none_or(|expected| expected == actual)
+        }
+
+        fn within<T: PartialOrd>(min: Option<T>, max: Option<T>, actual: Option<T>) -> bool {
+            if min.is_none() && max.is_none() {
+                return true;
+            }
+            let Some(actual) = actual else {
+                return false;
+            };
+            min.is_none_or(|min| actual >= min) && max.is_none_or(|max| actual <= max)
+        }
+
+        is(&self.status, &record.status)
+            && is(&self.tracked_download_status, &record.tracked_status)
+            && is(&self.tracked_download_state, &record.tracked_state)
+            && self
+                .reason
+                .as_ref()
+                .is_none_or(|reasons| reasons.contains(&record.reason))
+            && is(&self.protocol, &record.protocol)
+            && same(&self.indexer, &record.indexer)
+            && same(&self.download_client, &record.download_client)
+            && self.quality.as_ref().is_none_or(|quality| {
+                record
+                    .quality
+                    .as_ref()
+                    .is_some_and(|actual| quality.eq_ignore_ascii_case(actual))
+            })
+            && self.status_message.as_ref().is_none_or(|regex| {
+                record
+                    .status_messages
+                    .iter()
+                    .any(|message| regex.is_match(message))
+            })
+            && self.error_message.as_ref().is_none_or(|regex| {
+                record
+                    .error_message
+                    .as_ref()
+                    .is_some_and(|message| regex.is_match(message))
+            })
+            && within(
+                self.min_custom_format_score,
+                self.max_custom_format_score,
+                record.custom_format_score,
+            )
+            && within(self.min_size, self.max_size, Some(record.size))
+            && within(self.min_age, self.max_age, age(&record.added))
+    }
+}
+
+/// Time since an RFC 3339 timestamp, or None if it can't be parsed or is in the future
+fn age(timestamp: &str) -> Option<Duration> {
+    let added = DateTime::parse_from_rfc3339(timestamp).ok()?;
+    (Utc::now() - added.with_timezone(&Utc)).to_std().ok()
+}
+
+/// Flags for removing records from the queue, each unset one falling back to the next level:
+/// a rule's flags, then the instance's, then the defaults -- Ex:
+///
+/// ```toml
+/// [instances.removal]
+/// remove_from_client = true
+/// change_category = false
+/// skip_redownload = false
+/// ```
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemovalConfig {
+    /// Delete the download from the download client
+    pub remove_from_client: Option<bool>,
+    /// Blocklist the release. Rules with the `remove` action never blocklist.
+    pub blocklist: Option<bool>,
+    /// Move the download to the client's post-import category instead of removing it
+    pub change_category: Option<bool>,
+    /// Don't search for a replacement after blocklisting
+    pub skip_redownload: Option<bool>,
+}
+
+impl RemovalConfig {
+    /// Settings from `self`, with anything unset taken from `fallback`
+    pub fn or(&self, fallback: &RemovalConfig) -> RemovalConfig {
+        RemovalConfig {
+            remove_from_client: self.remove_from_client.or(fallback.remove_from_client),
+            blocklist: self.blocklist.or(fallback.blocklist),
+            change_category: self.change_category.or(fallback.change_category),
+            skip_redownload: self.skip_redownload.or(fallback.skip_redownload),
+        }
+    }
+
+    /// Final flags, blocklisting unless told otherwise
+    pub fn flags(&self) -> RemovalFlags {
+        RemovalFlags {
+            remove_from_client: self.remove_from_client.unwrap_or(false),
+            blocklist: self.blocklist.unwrap_or(true),
+            change_category: self.change_category.unwrap_or(false),
+            skip_redownload: self.skip_redownload.unwrap_or(false),
+        }
+    }
+}
+
+/// Indexers whose torrents have to keep seeding: their releases are blocklisted like any other,
+/// but the torrent is never removed from the client -- Ex:
+///
+/// ```toml
+/// [private_trackers]
+/// indexers = ["PrivateHD", "BTN"]
+/// change_category = true
+/// ```
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrivateTrackerConfig {
+    /// Indexer names, compared case-insensitively
+    #[serde(default)]
+    pub indexers: Vec<String>,
+    /// Move the torrent to the client's post-import category
+    #[serde(default)]
+    pub change_category: bool,
+}
+
+impl PrivateTrackerConfig {
+    pub fn is_private(&self, record: &Record) -> bool {
+        record.protocol == DownloadProtocol::Torrent
+            && self
+                .indexers
+                .iter()
+                .any(|indexer| indexer.eq_ignore_ascii_case(&record.indexer))
+    }
+
+    /// Flags that keep the record's torrent seeding if it's from a private tracker
+    pub fn protect(&self, record: &Record, mut flags: RemovalFlags) -> RemovalFlags {
+        if self.is_private(record) {
+            flags.remove_from_client = false;
+            flags.change_category |= self.change_category;
+        }
+        flags
+    }
+}
+
+/// Rules are checked in order and the first match wins.
+/// Records no configured rule matches fall through to the built-in rules,
+/// which blocklist anything with a "warning" status and stalled downloads.
+/// Files are only deleted for the failure reasons opted into, by the built-in rules as well as configured ones.
+#[derive(Default, Debug)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    builtin: Vec<Rule>,
+    /// The instance's removal flags, used where a rule doesn't set its own
+    removal: RemovalConfig,
+    /// Failure reasons `delete_file` may delete files for
+    delete_file_reasons: Vec<FailureReason>,
+    /// Torrents never removed from the client, whatever the flags say
+    private_trackers: PrivateTrackerConfig,
+    /// Replacements are searched for explicitly, so the instance never redownloads, whatever the flags say
+    search: bool,
+}
+
+/// What to do with a record, the rule that decided it and how to remove it
+#[derive(Debug, Clone, Copy)]
+pub struct Decision<'a> {
+    pub action: Action,
+    pub rule: &'a str,
+    pub flags: RemovalFlags,
+}
+
+impl RuleSet {
+    pub fn new(
+        rules: Vec<Rule>,
+        removal: RemovalConfig,
+        delete_file_reasons: Vec<FailureReason>,
+        private_trackers: PrivateTrackerConfig,
+        search: bool,
+    ) -> Self {
+        let builtin = vec![
+            Rule {
+                name: "warning status".to_string(),
+                action: Action::DeleteFile,
+                status: Some(QueueStatus::Warning),
+                ..Default::default()
+            },
+            Rule {
+                name: "warning tracked status".to_string(),
+                action: Action::DeleteFile,
+                tracked_download_status: Some(TrackedDownloadStatus::Warning),
+                ..Default::default()
+            },
+            Rule {
+                name: "stalled".to_string(),
+                action: Action::Blocklist,
+                reason: Some(vec![FailureReason::Stalled]),
+                ..Default::default()
+            },
+        ];
+
+        Self {
+            rules,
+            builtin,
+            removal,
+            delete_file_reasons,
+            private_trackers,
+            search,
+        }
+    }
+
+    /// The action for a record, the name of the rule that chose it and the flags to remove it with
+    pub fn decide(&self, record: &Record) -> Decision<'_> {
+        let Some(rule) = self
+            .rules
+            .iter()
+            .chain(&self.builtin)
+            .find(|rule| rule.matches(record))
+        else {
+            return Decision {
+                action: Action::Ignore,
+                rule: "no rule matched",
+                flags: self.removal.flags(),
+            };
+        };
+
+        let mut flags = rule.removal.or(&self.removal).flags();
+        if rule.action == Action::Remove {
+            flags.blocklist = false;
+        }
+        let mut flags = self.private_trackers.protect(record, flags);
+        // Otherwise the instance searches on its own as well, and every release is searched for twice
+        flags.skip_redownload |= self.search;
+        // The file on disk may well be a good one, so only delete it for reasons opted into
+        let action = match rule.action {
+            Action::DeleteFile if !self.delete_file_reasons.contains(&record.reason) => {
+                Action::Blocklist
+            }
+            action => action,
+        };
+        Decision {
+            action,
+            rule: &rule.name,
+            flags,
+        }
+    }
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|pattern| Regex::new(&pattern).map_err(D::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(toml: &str) -> Rule {
+        rule_with("blocklist", toml)
+    }
+
+    fn rule_with(action: &str, toml: &str) -> Rule {
+        toml::from_str(&format!(
+            "name = \"test\"\naction = \"{}\"\n{}",
+            action, toml
+        ))
+        .unwrap()
+    }
+
+    fn blocked(message: &str) -> Record {
+        let mut record = Record::example(1, "a");
+        record.status = QueueStatus::Completed;
+        record.tracked_state = TrackedDownloadState::ImportBlocked;
+        record.status_messages = vec![message.to_string()];
+        record.reason = FailureReason::classify(&record.status_messages, None);
+        record.indexer = "PrivateHD".to_string();
+        record.size = 1000;
+        record.added = (Utc::now() - chrono::Duration::hours(2)).to_rfc3339();
+        record
+    }
+
+    #[test]
+    fn empty_rule_matches_everything() {
+        assert!(rule("").matches(&blocked("Sample")));
+    }
+
+    #[test]
+    fn every_field_has_to_match() {
+        let rule = rule(
+            r#"
+            tracked_download_state = "importBlocked"
+            reason = ["not_an_upgrade"]
+            indexer = "privatehd"
+            "#,
+        );
+        assert!(rule.matches(&blocked("Not an upgrade for existing episode file(s)")));
+        assert!(!rule.matches(&blocked("Sample")));
+
+        let mut other_indexer = blocked("Not an upgrade for existing episode file(s)");
+        other_indexer.indexer = "NZBgeek".to_string();
+        assert!(!rule.matches(&other_indexer));
+    }
+
+    #[test]
+    fn matches_messages_by_regex() {
+        let status = rule(r#"status_message = "(?i)no files found are eligible""#);
+        assert!(status.matches(&blocked(
+            "No files found are eligible for import in /downloads/x"
+        )));
+        assert!(!status.matches(&blocked("Sample")));
+
+        let error = rule(r#"error_message = "missing""#);
+        assert!(!error.matches(&blocked("Sample")));
+    }
+
+    #[test]
+    fn ranges_need_the_value_known() {
+        let record = blocked("Sample");
+        assert!(rule("min_size = 1000").matches(&record));
+        assert!(!rule("max_size = 999").matches(&record));
+        assert!(!rule("min_custom_format_score = 0").matches(&record));
+        assert!(rule(r#"min_age = "1h""#).matches(&record));
+        assert!(!rule(r#"max_age = "1h""#).matches(&record));
+    }
+
+    #[test]
+    fn refuses_unknown_statuses() {
+        assert!(toml::from_str::<Rule>(
+            "name = \"test\"\naction = \"blocklist\"\nstatus = \"warnign\""
+        )
+        .is_err());
+    }
+
+    fn removal(toml: &str) -> RemovalConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn private_trackers() -> PrivateTrackerConfig {
+        toml::from_str("indexers = [\"privatehd\"]\nchange_category = true").unwrap()
+    }
+
+    fn torrent(indexer: &str) -> Record {
+        let mut record = blocked("Sample");
+        record.protocol = DownloadProtocol::Torrent;
+        record.indexer = indexer.to_string();
+        record
+    }
+
+    #[test]
+    fn rule_flags_win_over_instance_flags_over_defaults() {
+        let rules = RuleSet::new(
+            vec![rule_with(
+                "blocklist",
+                "reason = [\"sample\"]\nremoval = { remove_from_client = true }",
+            )],
+            removal("remove_from_client = false\nskip_redownload = true"),
+            Vec::new(),
+            PrivateTrackerConfig::default(),
+            false,
+        );
+
+        let flags = rules.decide(&blocked("Sample")).flags;
+        assert!(flags.remove_from_client && flags.skip_redownload);
+        assert!(flags.blocklist && !flags.change_category);
+
+        let flags = rules
+            .decide(&blocked("Not an upgrade for existing episode file(s)"))
+            .flags;
+        assert!(!flags.remove_from_client && flags.skip_redownload);
+    }
+
+    #[test]
+    fn searching_always_skips_redownload() {
+        let rules = RuleSet::new(
+            vec![
+                rule("reason = [\"sample\"]\nremoval = { skip_redownload = false }"),
+                rule("reason = [\"not_an_upgrade\"]"),
+            ],
+            removal("skip_redownload = false"),
+            Vec::new(),
+            PrivateTrackerConfig::default(),
+            true,
+        );
+        assert!(rules.decide(&blocked("Sample")).flags.skip_redownload);
+        assert!(
+            rules
+                .decide(&blocked("Not an upgrade for existing episode file(s)"))
+                .flags
+                .skip_redownload
+        );
+    }
+
+    #[test]
+    fn remove_never_blocklists() {
+        let rules = RuleSet::new(
+            vec![rule_with("remove", "removal = { blocklist = true }")],
+            removal("blocklist = true"),
+            Vec::new(),
+            PrivateTrackerConfig::default(),
+            false,
+        );
+        let decision = rules.decide(&blocked("Sample"));
+        assert_eq!(decision.action, Action::Remove);
+        assert!(!decision.flags.blocklist);
+    }
+
+    #[test]
+    fn delete_file_only_for_listed_reasons() {
+        let rules = RuleSet::new(
+            vec![rule_with("delete_file", "")],
+            RemovalConfig::default(),
+            vec![FailureReason::Sample],
+            PrivateTrackerConfig::default(),
+            false,
+        );
+        assert_eq!(rules.decide(&blocked("Sample")).action, Action::DeleteFile);
+        let decision = rules.decide(&blocked("Not an upgrade for existing episode file(s)"));
+        assert_eq!(decision.action, Action::Blocklist);
+        assert!(decision.flags.blocklist);
+    }
+
+    #[test]
+    fn private_torrents_keep_seeding() {
+        let rules = RuleSet::new(
+            vec![rule("removal = { remove_from_client = true }")],
+            removal("remove_from_client = true"),
+            Vec::new(),
+            private_trackers(),
+            false,
+        );
+        let flags = rules.decide(&torrent("PrivateHD")).flags;
+        assert!(!flags.remove_from_client);
+        assert!(flags.blocklist && flags.change_category);
+    }
+
+    #[test]
+    fn only_private_torrents_are_protected() {
+        let flags = RemovalConfig {
+            remove_from_client: Some(true),
+            ..Default::default()
+        }
+        .flags();
+        let private_trackers = private_trackers();
+
+        let public = private_trackers.protect(&torrent("Nyaa"), flags);
+        assert_eq!(public, flags);
+
+        let mut usenet = torrent("PrivateHD");
+        usenet.protocol = DownloadProtocol::Usenet;
+        assert!(!private_trackers.is_private(&usenet));
+        assert_eq!(private_trackers.protect(&usenet, flags), flags);
+    }
+}
@@ -0,0 +1,355 @@
+use std::{collections::HashMap, fs, io::ErrorKind, path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::Record,
+    config::deserialize_duration,
+    status::{DownloadProtocol, QueueStatus},
+};
+
+/// Stall thresholds for each download protocol -- Ex:
+///
+/// ```toml
+/// [stall.torrent]
+/// no_progress = "2h"
+/// max_eta = "2d"
+///
+/// [stall.usenet]
+/// no_progress = "30m"
+/// min_speed = 102400
+/// ```
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StallConfig {
+    #[serde(default)]
+    pub torrent: StallThresholds,
+    #[serde(default)]
+    pub usenet: StallThresholds,
+}
+
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StallThresholds {
+    /// Stalled if the remaining size hasn't gone down for this long
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub no_progress: Option<Duration>,
+    /// Stalled if the estimated time left is longer than this
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub max_eta: Option<Duration>,
+    /// Stalled if the average speed since the last check is below this many bytes per second
+    pub min_speed: Option<u64>,
+}
+
+impl StallConfig {
+    /// Whether a threshold compares against earlier checks, and so needs progress remembered between runs
+    pub fn needs_history(&self) -> bool {
+        [&self.torrent, &self.usenet]
+            .iter()
+            .any(|thresholds| thresholds.no_progress.is_some() || thresholds.min_speed.is_some())
+    }
+}
+
+/// Progress of a download the last time it was checked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Progress {
+    sizeleft: i64,
+    seen: DateTime<Utc>,
+    progressed: DateTime<Utc>,
+}
+
+/// Remembers how downloads progress between checks to find the ones that stopped moving.
+/// Progress is kept for every instance, keyed by instance name then `Record::download_key`,
+/// and saved as JSON so it survives restarts.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct StallTracker {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Progress as of the last completed check of each instance
+    instances: HashMap<String, HashMap<String, Progress>>,
+    /// Progress seen so far in the current check of an instance
+    #[serde(skip)]
+    current: HashMap<String, Progress>,
+}
+
+impl StallTracker {
+    /// Load progress from `path`, starting empty if it doesn't exist yet.
+    /// Without a path progress is only kept in memory.
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+
+        let mut tracker = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("failed to parse stalls file {}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read stalls file {}", path.display()))
+            }
+        };
+        tracker.path = Some(path);
+        Ok(tracker)
+    }
+
+    /// Write progress to disk, replacing the file in one step so a crash can't leave it half written
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write stalls file {}", temp.display()))?;
+        fs::rename(&temp, path)
+            .with_context(|| format!("failed to write stalls file {}", path.display()))
+    }
+
+    /// Record the progress of every downloading record and describe why each stalled one is stalled.
+    /// Can be called several times per check, once for each page of the queue.
+    pub fn check(
+        &mut self,
+        instance: &str,
+        config: &StallConfig,
+        records: &[Record],
+    ) -> HashMap<i64, String> {
+        let now = Utc::now();
+        let mut stalled = HashMap::new();
+        let downloads = self.instances.get(instance);
+
+        for record in records {
+            if record.status != QueueStatus::Downloading {
+                continue;
+            }
+
+            let key = record.download_key();
+            let previous = downloads.and_then(|downloads| downloads.get(&key));
+            let progress = Progress {
+                sizeleft: record.sizeleft,
+                seen: now,
+                progressed: match previous {
+                    Some(previous) if record.sizeleft >= previous.sizeleft => previous.progressed,
+                    _ => now,
+                },
+            };
+
+            if let Some(reason) = stall_reason(config, record, previous, &progress) {
+                stalled.insert(record.id, reason);
+            }
+            self.current.insert(key, progress);
+        }
+
+        stalled
+    }
+
+    /// Forget downloads that weren't seen during the check of an instance.
+    /// If the check didn't see the whole queue, keep what was known before instead.
+    pub fn end_check(&mut self, instance: &str, completed: bool) {
+        let current = std::mem::take(&mut self.current);
+        if completed {
+            self.instances.insert(instance.to_string(), current);
+        }
+    }
+}
+
+fn stall_reason(
+    config: &StallConfig,
+    record: &Record,
+    previous: Option<&Progress>,
+    progress: &Progress,
+) -> Option<String> {
+    let thresholds = match record.protocol {
+        DownloadProtocol::Torrent => &config.torrent,
+        DownloadProtocol::Usenet => &config.usenet,
+        DownloadProtocol::Unknown(_) => return None,
+    };
+
+    if let Some(no_progress) = thresholds.no_progress {
+        let idle = (progress.seen - progress.progressed)
+            .to_std()
+            .unwrap_or_default();
+        if idle >= no_progress {
+            return Some(format!(
+                "no progress for {}",
+                humantime::format_duration(round(idle))
+            ));
+        }
+    }
+
+    if let (Some(max_eta), Some(eta)) = (thresholds.max_eta, eta(record)) {
+        if eta > max_eta {
+            return Some(format!("ETA {}", humantime::format_duration(round(eta))));
+        }
+    }
+
+    if let (Some(min_speed), Some(previous)) = (thresholds.min_speed, previous) {
+        let elapsed = (progress.seen - previous.seen).num_seconds();
+        if elapsed > 0 {
+            let speed = (previous.sizeleft - progress.sizeleft).max(0) as u64 / elapsed as u64;
+            if speed < min_speed {
+                return Some(format!("{} B/s", speed));
+            }
+        }
+    }
+
+    None
+}
+
+/// Estimated time left, from `timeleft` ("[d.]hh:mm:ss[.fffffff]") or else `estimated_completion_time`
+fn eta(record: &Record) -> Option<Duration> {
+    if let Some(timeleft) = &record.timeleft {
+        let (days, time) = match timeleft.split_once(':') {
+            Some((hours, _)) if hours.contains('.') => timeleft.split_once('.')?,
+            _ => ("0", timeleft.as_str()),
+        };
+        let time = time.split_once('.').map_or(time, |(time, _)| time);
+        let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+        let (Ok(days), Some(Some(hours)), Some(Some(minutes)), Some(Some(seconds))) = (
+            days.parse::<u64>(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return None;
+        };
+        return Some(Duration::from_secs(
+            ((days * 24 + hours) * 60 + minutes) * 60 + seconds,
+        ));
+    }
+
+    let completion =
+        DateTime::parse_from_rfc3339(record.estimated_completion_time.as_ref()?).ok()?;
+    (completion.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// Drop sub-second precision so durations print cleanly
+fn round(duration: Duration) -> Duration {
+    Duration::from_secs(duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eta_of(timeleft: &str) -> Option<Duration> {
+        let mut record = Record::example(1, "a");
+        record.timeleft = Some(timeleft.to_string());
+        eta(&record)
+    }
+
+    #[test]
+    fn parses_timeleft() {
+        assert_eq!(eta_of("00:10:00"), Some(Duration::from_secs(600)));
+        assert_eq!(eta_of("12:34:56"), Some(Duration::from_secs(45_296)));
+    }
+
+    #[test]
+    fn parses_day_prefixed_timeleft() {
+        assert_eq!(
+            eta_of("1.02:03:04"),
+            Some(Duration::from_secs(86_400 + 7_384))
+        );
+        assert_eq!(
+            eta_of("14.00:00:00"),
+            Some(Duration::from_secs(14 * 86_400))
+        );
+    }
+
+    #[test]
+    fn ignores_fractional_seconds() {
+        assert_eq!(eta_of("00:00:05.1234567"), Some(Duration::from_secs(5)));
+        assert_eq!(
+            eta_of("2.00:00:05.5"),
+            Some(Duration::from_secs(2 * 86_400 + 5))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_timeleft() {
+        assert_eq!(eta_of(""), None);
+        assert_eq!(eta_of("10:00"), None);
+        assert_eq!(eta_of("x.01:00:00"), None);
+    }
+
+    #[test]
+    fn stalls_on_long_eta() {
+        let config = StallConfig {
+            torrent: StallThresholds {
+                max_eta: Some(Duration::from_secs(86_400)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut tracker = StallTracker::default();
+        let mut slow = Record::example(1, "slow");
+        slow.status = QueueStatus::Downloading;
+        slow.protocol = DownloadProtocol::Torrent;
+        slow.timeleft = Some("3.00:00:00".to_string());
+        let mut fast = Record::example(2, "fast");
+        fast.status = QueueStatus::Downloading;
+        fast.protocol = DownloadProtocol::Torrent;
+        fast.timeleft = Some("01:00:00".to_string());
+
+        let stalled = tracker.check("tv", &config, &[slow, fast]);
+        assert_eq!(stalled.get(&1).map(String::as_str), Some("ETA 3days"));
+        assert!(!stalled.contains_key(&2));
+    }
+
+    fn downloading(id: i64, download_id: &str, sizeleft: i64) -> Record {
+        let mut record = Record::example(id, download_id);
+        record.status = QueueStatus::Downloading;
+        record.protocol = DownloadProtocol::Usenet;
+        record.sizeleft = sizeleft;
+        record
+    }
+
+    #[test]
+    fn remembers_progress_across_runs() {
+        let path = std::env::temp_dir().join(format!("stalls-{}.json", std::process::id()));
+        let config = StallConfig {
+            usenet: StallThresholds {
+                no_progress: Some(Duration::from_nanos(1)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut tracker = StallTracker::load(Some(path.clone())).unwrap();
+        assert!(tracker
+            .check("tv", &config, &[downloading(1, "a", 100)])
+            .is_empty());
+        tracker.end_check("tv", true);
+        tracker.save().unwrap();
+
+        // A later run picks up where the last one left off
+        let mut tracker = StallTracker::load(Some(path.clone())).unwrap();
+        let stalled = tracker.check("tv", &config, &[downloading(1, "a", 100)]);
+        fs::remove_file(&path).unwrap();
+        assert!(stalled[&1].starts_with("no progress for"));
+    }
+
+    #[test]
+    fn records_without_download_id_are_tracked_apart() {
+        let config = StallConfig {
+            usenet: StallThresholds {
+                no_progress: Some(Duration::from_nanos(1)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut tracker = StallTracker::default();
+        tracker.check("tv", &config, &[downloading(1, "", 100)]);
+        tracker.end_check("tv", true);
+
+        let stalled = tracker.check(
+            "tv",
+            &config,
+            &[downloading(1, "", 100), downloading(2, "", 100)],
+        );
+        assert!(stalled.contains_key(&1));
+        assert!(!stalled.contains_key(&2));
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+/// An enum of the values the API sends for a field, matched case-insensitively.
+/// Values added upstream are kept in `Unknown` instead of failing the whole response.
+macro_rules! api_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident => $value:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum $name {
+            $($variant,)*
+            Unknown(String),
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Self::$variant => $value,)*
+                    Self::Unknown(value) => value,
+                }
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(value: &str) -> Self {
+                $(if value.eq_ignore_ascii_case($value) {
+                    return Self::$variant;
+                })*
+                Self::Unknown(value.to_string())
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::Unknown(String::new())
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Ok(Self::from(String::deserialize(deserializer)?.as_str()))
+            }
+        }
+
+        impl Known for $name {
+            const VALUES: &'static [&'static str] = &[$($value),*];
+
+            fn is_unknown(&self) -> bool {
+                matches!(self, Self::Unknown(_))
+            }
+        }
+    };
+}
+
+/// Implemented by every API enum, so config values can be checked against the known ones
+pub trait Known: Sized {
+    /// Every value that isn't `Unknown`
+    const VALUES: &'static [&'static str];
+
+    fn is_unknown(&self) -> bool;
+}
+
+/// Deserialize an optional API enum from the config, refusing values the API never sends
+pub fn deserialize_known<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Known + Deserialize<'de> + fmt::Display,
+{
+    match Option::<T>::deserialize(deserializer)? {
+        Some(value) if value.is_unknown() => Err(D::Error::custom(format!(
+            "unknown value `{}`, expected one of {}",
+            value,
+            T::VALUES.join(", ")
+        ))),
+        value => Ok(value),
+    }
+}
+
+api_enum! {
+    /// Status of the download in the download client
+    QueueStatus {
+        Queued => "queued",
+        Paused => "paused",
+        Downloading => "downloading",
+        Completed => "completed",
+        Delay => "delay",
+        DownloadClientUnavailable => "downloadClientUnavailable",
+        Warning => "warning",
+        Failed => "failed",
+        Fallback => "fallback",
+    }
+}
+
+api_enum! {
+    /// Whether anything went wrong while tracking the download
+    TrackedDownloadStatus {
+        Ok => "ok",
+        Warning => "warning",
+        Error => "error",
+    }
+}
+
+api_enum! {
+    /// Where the download is in the import process
+    TrackedDownloadState {
+        Downloading => "downloading",
+        ImportBlocked => "importBlocked",
+        ImportPending => "importPending",
+        Importing => "importing",
+        Imported => "imported",
+        FailedPending => "failedPending",
+        Failed => "failed",
+        Ignored => "ignored",
+    }
+}
+
+api_enum! {
+    DownloadProtocol {
+        Usenet => "usenet",
+        Torrent => "torrent",
+    }
+}
+
+api_enum! {
+    /// Progress of a command, like a search
+    CommandStatus {
+        Queued => "queued",
+        Started => "started",
+        Completed => "completed",
+        Failed => "failed",
+        Aborted => "aborted",
+        Cancelled => "cancelled",
+        Orphaned => "orphaned",
+    }
+}
+
+impl CommandStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, Self::Queued | Self::Started)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config field holding an API enum
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        #[serde(default, deserialize_with = "deserialize_known")]
+        state: Option<TrackedDownloadState>,
+    }
+
+    #[test]
+    fn parses_values_in_any_case() {
+        let parse = |value: &str| serde_json::from_value::<TrackedDownloadState>(value.into());
+        assert_eq!(
+            parse("importBlocked").unwrap(),
+            TrackedDownloadState::ImportBlocked
+        );
+        assert_eq!(
+            parse("IMPORTBLOCKED").unwrap(),
+            TrackedDownloadState::ImportBlocked
+        );
+        assert_eq!(
+            parse("importblocked").unwrap(),
+            TrackedDownloadState::ImportBlocked
+        );
+        // Always written back the way the API sends it
+        assert_eq!(
+            TrackedDownloadState::from("IMPORTBLOCKED").to_string(),
+            "importBlocked"
+        );
+    }
+
+    #[test]
+    fn keeps_new_values_as_unknown() {
+        let status: QueueStatus = serde_json::from_value("rerouted".into()).unwrap();
+        assert_eq!(status, QueueStatus::Unknown("rerouted".to_string()));
+        assert!(status.is_unknown());
+        assert_eq!(status.to_string(), "rerouted");
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            serde_json::Value::from("rerouted")
+        );
+    }
+
+    #[test]
+    fn config_refuses_values_the_api_never_sends() {
+        let config: Config = toml::from_str(r#"state = "ImportPending""#).unwrap();
+        assert_eq!(config.state, Some(TrackedDownloadState::ImportPending));
+
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.state, None);
+
+        let error = toml::from_str::<Config>(r#"state = "import_pending""#).unwrap_err();
+        assert!(
+            error.to_string().contains(
+                "unknown value `import_pending`, expected one of downloading, importBlocked, \
+                 importPending, importing, imported, failedPending, failed, ignored"
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn only_queued_and_started_commands_are_running() {
+        assert!(!CommandStatus::Queued.is_finished());
+        assert!(!CommandStatus::Started.is_finished());
+        assert!(CommandStatus::Completed.is_finished());
+        assert!(CommandStatus::Failed.is_finished());
+        assert!(CommandStatus::Unknown("paused".to_string()).is_finished());
+    }
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashMap, fs, io::ErrorKind, path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How long a record has to stay bad before it's acted on
+#[derive(Debug, Clone)]
+pub struct StrikePolicy {
+    /// Consecutive checks a record has to fail
+    pub strikes: u32,
+    /// Time since the first failed check
+    pub duration: Option<Duration>,
+}
+
+impl StrikePolicy {
+    /// Whether the policy ever holds a record back, and so needs strikes remembered between runs
+    pub fn is_enabled(&self) -> bool {
+        self.strikes > 1 || self.duration.is_some()
+    }
+
+    pub fn is_ready(&self, strike: &Strike) -> bool {
+        let bad_for = (strike.last - strike.first).to_std().unwrap_or_default();
+        strike.count >= self.strikes && self.duration.is_none_or(|duration| bad_for >= duration)
+    }
+}
+
+/// Consecutive failed checks of a download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Strike {
+    pub count: u32,
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+}
+
+/// Strikes for every instance, keyed by instance name then `Record::download_key`.
+/// Saved as JSON so they survive restarts.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct StrikeStore {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    instances: HashMap<String, HashMap<String, Strike>>,
+    /// Strikes counted so far in the current check of an instance
+    #[serde(skip)]
+    pending: HashMap<String, Strike>,
+}
+
+impl StrikeStore {
+    /// Load strikes from `path`, starting empty if it doesn't exist yet.
+    /// Without a path strikes are only kept in memory.
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+
+        let mut store = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("failed to parse strikes file {}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read strikes file {}", path.display()))
+            }
+        };
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    /// Write strikes to disk, replacing the file in one step so a crash can't leave it half written
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write strikes file {}", temp.display()))?;
+        fs::rename(&temp, path)
+            .with_context(|| format!("failed to write strikes file {}", path.display()))
+    }
+
+    /// Add a strike to every bad download.
+    /// Can be called several times per check, once for each page of the queue.
+    /// Returns the strikes counted so far in this check.
+    pub fn update<'a>(
+        &mut self,
+        instance: &str,
+        bad_downloads: impl IntoIterator<Item = &'a str>,
+    ) -> &HashMap<String, Strike> {
+        let now = Utc::now();
+        let previous = self.instances.get(instance);
+
+        for download_id in bad_downloads {
+            // Season packs share a download id but only count once per check
+            if self.pending.contains_key(download_id) {
+                continue;
+            }
+            let strike = match previous.and_then(|previous| previous.get(download_id)) {
+                Some(strike) => Strike {
+                    count: strike.count + 1,
+                    first: strike.first,
+                    last: now,
+                },
+                None => Strike {
+                    count: 1,
+                    first: now,
+                    last: now,
+                },
+            };
+            self.pending.insert(download_id.to_string(), strike);
+        }
+
+        &self.pending
+    }
+
+    /// Keep only the strikes counted during the check, forgetting downloads that recovered or left the queue.
+    /// If the check didn't see the whole queue, keep the strikes from before instead.
+    pub fn end_check(&mut self, instance: &str, completed: bool) {
+        let pending = std::mem::take(&mut self.pending);
+        if completed {
+            self.instances.insert(instance.to_string(), pending);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Record;
+
+    fn strike(count: u32, bad_for: Duration) -> Strike {
+        let last = Utc::now();
+        Strike {
+            count,
+            first: last - chrono::Duration::from_std(bad_for).unwrap(),
+            last,
+        }
+    }
+
+    #[test]
+    fn counts_consecutive_checks() {
+        let mut store = StrikeStore::default();
+        for count in 1..=3 {
+            let strikes = store.update("tv", ["a", "a"]);
+            assert_eq!(strikes["a"].count, count);
+            store.end_check("tv", true);
+        }
+    }
+
+    #[test]
+    fn resets_on_recovery() {
+        let mut store = StrikeStore::default();
+        store.update("tv", ["a", "b"]);
+        store.end_check("tv", true);
+
+        // "a" recovered, so it starts over the next time it's bad
+        store.update("tv", ["b"]);
+        store.end_check("tv", true);
+        let strikes = store.update("tv", ["a", "b"]);
+        assert_eq!(strikes["a"].count, 1);
+        assert_eq!(strikes["b"].count, 3);
+    }
+
+    #[test]
+    fn incomplete_check_keeps_strikes() {
+        let mut store = StrikeStore::default();
+        store.update("tv", ["a"]);
+        store.end_check("tv", true);
+
+        store.update("tv", std::iter::empty());
+        store.end_check("tv", false);
+        assert_eq!(store.update("tv", ["a"])["a"].count, 2);
+    }
+
+    #[test]
+    fn records_without_download_id_are_counted_apart() {
+        let pending = Record::example(1, "");
+        let delayed = Record::example(2, "");
+        let mut store = StrikeStore::default();
+        store.update("tv", [pending.download_key().as_str()]);
+        store.end_check("tv", true);
+
+        let keys = [pending.download_key(), delayed.download_key()];
+        let strikes = store.update("tv", keys.iter().map(String::as_str));
+        assert_eq!(strikes.len(), 2);
+        assert_eq!(strikes[&keys[0]].count, 2);
+        assert_eq!(strikes[&keys[1]].count, 1);
+    }
+
+    #[test]
+    fn instances_are_counted_apart() {
+        let mut store = StrikeStore::default();
+        store.update("tv", ["a"]);
+        store.end_check("tv", true);
+        assert_eq!(store.update("4k", ["a"])["a"].count, 1);
+    }
+
+    #[test]
+    fn ready_after_strikes_and_duration() {
+        let policy = StrikePolicy {
+            strikes: 3,
+            duration: Some(Duration::from_secs(1800)),
+        };
+        assert!(!policy.is_ready(&strike(2, Duration::from_secs(3600))));
+        assert!(!policy.is_ready(&strike(3, Duration::from_secs(600))));
+        assert!(policy.is_ready(&strike(3, Duration::from_secs(1800))));
+
+        let strikes_only = StrikePolicy {
+            strikes: 1,
+            duration: None,
+        };
+        assert!(!strikes_only.is_enabled());
+        assert!(strikes_only.is_ready(&strike(1, Duration::ZERO)));
+    }
+}